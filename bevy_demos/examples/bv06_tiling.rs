@@ -1,5 +1,6 @@
 use bevy::{prelude::*, window::PresentMode};
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 const TITLE: &str = "bv06 Tiling";
 const WIN_W: f32 = 1280.;
@@ -14,8 +15,21 @@ struct Bird;
 #[derive(Component)]
 struct Brick;
 
+// same seed, same numbers, every run and on every machine
+#[derive(Resource, Deref, DerefMut)]
+struct SeededRng(ChaCha8Rng);
+
 fn main() {
+    // pass a seed to get the same layout again: cargo run --example bv06_tiling -- 42
+    // otherwise pick one and print it
+    let seed = std::env::args()
+        .nth(1)
+        .map(|arg| arg.parse().expect("seed should be a number"))
+        .unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+
     App::new()
+        .insert_resource(SeededRng(ChaCha8Rng::seed_from_u64(seed)))
         .insert_resource(ClearColor(Color::Srgba(Srgba::gray(0.25))))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -34,6 +48,7 @@ fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut rng: ResMut<SeededRng>,
 ) {
     let bird_sheet_handle = asset_server.load("birds.png");
     let bird_layout = TextureAtlasLayout::from_grid(UVec2::splat(TILE_SIZE), 2, 2, None, None);     // 2x2 grid of 100x100px images
@@ -47,7 +62,6 @@ fn setup(
 
     commands.spawn(Camera2dBundle::default());
 
-    let mut rng = rand::thread_rng();
     let x_bound = WIN_W / 2. - (TILE_SIZE as f32) / 2.; // spawned in center of screen, this sets the x bound (window_width/2 - tile_size/2)
     let y_bound = WIN_H / 2. - (TILE_SIZE as f32) / 2.; // sets the y bounds
 
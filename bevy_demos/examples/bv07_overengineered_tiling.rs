@@ -1,5 +1,6 @@
 use bevy::{prelude::*, window::PresentMode};
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use std::collections::HashMap;
 
 const TITLE: &str = "bv07 Tiling";
@@ -15,8 +16,21 @@ struct Bird;
 #[derive(Component)]
 struct Brick;
 
+// same seed, same numbers, every run and on every machine
+#[derive(Resource, Deref, DerefMut)]
+struct SeededRng(ChaCha8Rng);
+
 fn main() {
+    // pass a seed to get the same layout again: cargo run --example bv07_overengineered_tiling -- 42
+    // otherwise pick one and print it
+    let seed = std::env::args()
+        .nth(1)
+        .map(|arg| arg.parse().expect("seed should be a number"))
+        .unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+
     App::new()
+        .insert_resource(SeededRng(ChaCha8Rng::seed_from_u64(seed)))
         .insert_resource(ClearColor(Color::Srgba(Srgba::gray(0.25))))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -47,6 +61,7 @@ fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut rng: ResMut<SeededRng>,
 ) {
     let sheets_data: HashMap<_, _> = [SheetTypes::Bird, SheetTypes::Brick]
         .into_iter()
@@ -71,7 +86,6 @@ fn setup(
 
     commands.spawn(Camera2dBundle::default());
 
-    let mut rng = rand::thread_rng();
     let x_bound = WIN_W / 2. - (TILE_SIZE as f32) / 2.;
     let y_bound = WIN_H / 2. - (TILE_SIZE as f32) / 2.;
 
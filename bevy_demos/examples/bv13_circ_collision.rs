@@ -8,10 +8,19 @@ const PLAYER_SIZE: f32 = 32.;
 const PLAYER_SPEED: f32 = 300.;
 // 1px/frame^2 @60Hz == 3600px/s^2
 const ACCEL_RATE: f32 = 3600.;
+// Movement and collision steps per second, the same on any display
+const FIXED_HZ: f64 = 60.;
 
 #[derive(Component)]
 struct Player;
 
+// Drawn between the last two fixed steps, see bv12
+#[derive(Component)]
+struct Interpolated {
+    previous: Vec3,
+    current: Vec3,
+}
+
 #[derive(Component)]
 struct Velocity {
     velocity: Vec2,
@@ -60,8 +69,13 @@ fn main() {
             }),
             ..default()
         }))
+        .insert_resource(Time::<Fixed>::from_hz(FIXED_HZ))
         .add_systems(Startup, setup)
-        .add_systems(Update, move_player)
+        // Time here is fixed time, so every step is the same length
+        .add_systems(FixedFirst, restore_translation)
+        .add_systems(FixedUpdate, move_player)
+        .add_systems(FixedLast, store_translation)
+        .add_systems(Update, interpolate_translation)
         .run();
 }
 
@@ -79,6 +93,10 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         })
         .insert(Velocity::new())
         .insert(Circle::new(PLAYER_SIZE / 2.))
+        .insert(Interpolated {
+            previous: Vec3::new(-WIN_W / 4., 0., 0.),
+            current: Vec3::new(-WIN_W / 4., 0., 0.),
+        })
         .insert(Player);
 
     commands
@@ -94,6 +112,29 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         .insert(Dot);
 }
 
+fn restore_translation(mut player: Query<(&mut Transform, &mut Interpolated)>) {
+    for (mut pt, mut pi) in player.iter_mut() {
+        pi.previous = pi.current;
+        pt.translation = pi.current;
+    }
+}
+
+fn store_translation(mut player: Query<(&Transform, &mut Interpolated)>) {
+    for (pt, mut pi) in player.iter_mut() {
+        pi.current = pt.translation;
+    }
+}
+
+fn interpolate_translation(
+    time: Res<Time<Fixed>>,
+    mut player: Query<(&mut Transform, &Interpolated)>,
+) {
+    let t = time.overstep_fraction();
+    for (mut pt, pi) in player.iter_mut() {
+        pt.translation = pi.previous.lerp(pi.current, t);
+    }
+}
+
 fn move_player(
     time: Res<Time>,
     input: Res<ButtonInput<KeyCode>>,
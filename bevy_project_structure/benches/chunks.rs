@@ -0,0 +1,118 @@
+// Measures chunk streaming on generated levels of growing length, without a window:
+//   cargo bench --bench chunks
+use bevy::{prelude::*, state::app::StatesPlugin, utils::HashMap};
+use std::time::{Duration, Instant};
+
+use bevy_project_structure::{
+    chunks::{ChunkPlugin, LoadedChunks},
+    level::{
+        tile_to_world, ActiveLevel, BackgroundImage, BrickSheet, BrickSheets, PropertyComponents,
+    },
+    level_data::LevelData,
+    levelgen,
+    loading::AssetsReloaded,
+    GameState, PLAYER_SPEED,
+};
+
+const SEED: u64 = 1;
+const LENGTHS: [usize; 4] = [50, 500, 5_000, 50_000];
+const FRAME_TIME: f32 = 1. / 60.;
+// Entity counts are reported at each of these fractions of the way along
+const CHECKPOINTS: [f32; 4] = [0.25, 0.5, 0.75, 1.];
+
+// Scrolls a camera from one end of each generated level to the other at running
+// speed, and reports how many entities the level needed along the way and how
+// long each frame took. With chunk streaming both should stay flat however long
+// the level gets.
+fn main() {
+    println!(
+        "{:>8} {:>10} {:>10} {:>28} {:>10} {:>12} {:>12}",
+        "length",
+        "tiles",
+        "frames",
+        "entities at 1/4, 1/2, 3/4, end",
+        "max tiles",
+        "mean frame",
+        "worst frame"
+    );
+
+    for length in LENGTHS {
+        let level = levelgen::generate(SEED, length, &default());
+        let start_x = tile_to_world(Vec2::ZERO).x;
+        let end_x = tile_to_world(Vec2::new(level.width as f32 - 1., 0.)).x;
+        let frames = ((end_x - start_x) / (PLAYER_SPEED * FRAME_TIME)).ceil() as usize;
+        let tile_total: usize = level
+            .layers
+            .iter()
+            .map(|layer| layer.tiles.iter().flatten().count())
+            .sum();
+        let tilesets: HashMap<String, BrickSheet> = level
+            .layers
+            .iter()
+            .map(|layer| {
+                let sheet = BrickSheet(Handle::default(), Handle::default());
+                (layer.tileset.clone(), sheet)
+            })
+            .collect();
+
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            StatesPlugin,
+            TransformPlugin,
+        ))
+        .init_asset::<LevelData>()
+        .add_event::<AssetsReloaded>()
+        .insert_state(GameState::Playing)
+        .add_plugins(ChunkPlugin)
+        .insert_resource(BrickSheets(tilesets))
+        .insert_resource(BackgroundImage(Handle::default()))
+        .init_resource::<PropertyComponents>();
+
+        let handle = app
+            .world_mut()
+            .resource_mut::<Assets<LevelData>>()
+            .add(level);
+        app.insert_resource(ActiveLevel(handle));
+        let camera = app
+            .world_mut()
+            .spawn((
+                Camera::default(),
+                OrthographicProjection::default(),
+                TransformBundle::from_transform(Transform::from_xyz(start_x, 0., 0.)),
+            ))
+            .id();
+
+        let mut entities = Vec::new();
+        let mut max_tiles = 0;
+        let mut total = Duration::ZERO;
+        let mut worst = Duration::ZERO;
+        for frame in 1..=frames {
+            let start = Instant::now();
+            app.update();
+            let elapsed = start.elapsed();
+            total += elapsed;
+            worst = worst.max(elapsed);
+
+            let world = app.world_mut();
+            let checkpoint = CHECKPOINTS[entities.len()];
+            if frame >= (frames as f32 * checkpoint) as usize {
+                entities.push(world.entities().len().to_string());
+            }
+            max_tiles = max_tiles.max(world.resource::<LoadedChunks>().tile_count());
+            world.get_mut::<Transform>(camera).unwrap().translation.x += PLAYER_SPEED * FRAME_TIME;
+        }
+
+        println!(
+            "{:>8} {:>10} {:>10} {:>28} {:>10} {:>9.3} ms {:>9.3} ms",
+            length,
+            tile_total,
+            frames,
+            entities.join(", "),
+            max_tiles,
+            total.as_secs_f64() * 1000. / frames as f64,
+            worst.as_secs_f64() * 1000.,
+        );
+    }
+}
@@ -0,0 +1,865 @@
+use bevy::{
+    ecs::system::SystemParam,
+    input::{
+        gamepad::{GamepadConnection, GamepadConnectionEvent},
+        InputSystem,
+    },
+    prelude::*,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, fs};
+
+use crate::{AXIS_THRESHOLD, BINDINGS_PATH, STICK_DEADZONE};
+
+// What the player wants to do, whatever device it comes from. Gameplay reads
+// these through Res<ButtonInput<Action>> instead of looking at keys.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Pause,
+    Confirm,
+    MenuUp,
+    MenuDown,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Pause,
+        Action::Confirm,
+        Action::MenuUp,
+        Action::MenuDown,
+    ];
+    // The ones that matter on a fixed tick, and so are saved in recordings
+    pub const TICKED: [Action; 5] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Pause,
+        Action::Confirm,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Jump => "Jump",
+            Action::Pause => "Pause",
+            Action::Confirm => "Confirm",
+            Action::MenuUp => "Menu up",
+            Action::MenuDown => "Menu down",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButtonType),
+    // A stick pushed past AXIS_THRESHOLD, in the direction of the sign (1 or -1)
+    GamepadAxis(GamepadAxisType, f32),
+}
+
+impl Binding {
+    fn is_gamepad(&self) -> bool {
+        matches!(self, Binding::GamepadButton(_) | Binding::GamepadAxis(..))
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Mouse(button) => write!(f, "Mouse {:?}", button),
+            Binding::GamepadButton(button) => write!(f, "Pad {:?}", button),
+            Binding::GamepadAxis(axis, sign) => {
+                write!(f, "Pad {:?}{}", axis, if *sign < 0. { "-" } else { "+" })
+            }
+        }
+    }
+}
+
+// Every action can have any number of bindings, from any device
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Deref, DerefMut)]
+#[serde(default)]
+pub struct InputBindings {
+    // Stick travel ignored around the centre, as a fraction of full tilt
+    pub deadzone: f32,
+    #[deref]
+    pub actions: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use Binding::*;
+        let actions = BTreeMap::from([
+            (
+                Action::MoveLeft,
+                vec![
+                    Key(KeyCode::KeyA),
+                    Key(KeyCode::ArrowLeft),
+                    GamepadButton(GamepadButtonType::DPadLeft),
+                    GamepadAxis(GamepadAxisType::LeftStickX, -1.),
+                ],
+            ),
+            (
+                Action::MoveRight,
+                vec![
+                    Key(KeyCode::KeyD),
+                    Key(KeyCode::ArrowRight),
+                    GamepadButton(GamepadButtonType::DPadRight),
+                    GamepadAxis(GamepadAxisType::LeftStickX, 1.),
+                ],
+            ),
+            (
+                Action::Jump,
+                vec![
+                    Key(KeyCode::Space),
+                    Key(KeyCode::KeyW),
+                    GamepadButton(GamepadButtonType::South),
+                ],
+            ),
+            (
+                Action::Pause,
+                vec![
+                    Key(KeyCode::Escape),
+                    GamepadButton(GamepadButtonType::Start),
+                ],
+            ),
+            (
+                Action::Confirm,
+                vec![
+                    Key(KeyCode::Enter),
+                    Mouse(MouseButton::Left),
+                    GamepadButton(GamepadButtonType::South),
+                ],
+            ),
+            (
+                Action::MenuUp,
+                vec![
+                    Key(KeyCode::ArrowUp),
+                    Key(KeyCode::KeyW),
+                    GamepadButton(GamepadButtonType::DPadUp),
+                    GamepadAxis(GamepadAxisType::LeftStickY, 1.),
+                ],
+            ),
+            (
+                Action::MenuDown,
+                vec![
+                    Key(KeyCode::ArrowDown),
+                    Key(KeyCode::KeyS),
+                    GamepadButton(GamepadButtonType::DPadDown),
+                    GamepadAxis(GamepadAxisType::LeftStickY, -1.),
+                ],
+            ),
+        ]);
+        Self {
+            deadzone: STICK_DEADZONE,
+            actions,
+        }
+    }
+}
+
+// How far each action is pushed, from 0 to 1. Keys and buttons are all or
+// nothing, while sticks rise smoothly from the edge of the deadzone.
+#[derive(Resource, Clone, Default, Debug)]
+pub struct ActionValues(HashMap<Action, f32>);
+
+impl ActionValues {
+    pub fn get(&self, action: Action) -> f32 {
+        self.0.get(&action).copied().unwrap_or(0.)
+    }
+}
+
+// The actions as gameplay on the fixed timestep sees them, updated once per tick.
+// Presses since the last tick carry over to the next one, so a quick tap between
+// ticks isn't lost and a frame with two ticks doesn't see the same press twice.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct TickActions {
+    #[deref]
+    actions: ButtonInput<Action>,
+    pub values: ActionValues,
+    pressed_since_tick: Vec<Action>,
+}
+
+// Everything gameplay can read from TickActions on one tick, in a form that can
+// be saved and played back
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct TickInput {
+    // A bit for each action, in Action::TICKED order
+    pressed: u8,
+    just_pressed: u8,
+    values: [f32; Action::TICKED.len()],
+}
+
+impl TickActions {
+    pub fn input(&self) -> TickInput {
+        let mut input = TickInput::default();
+        for (i, action) in Action::TICKED.into_iter().enumerate() {
+            input.pressed |= u8::from(self.pressed(action)) << i;
+            input.just_pressed |= u8::from(self.just_pressed(action)) << i;
+            input.values[i] = self.values.get(action);
+        }
+        input
+    }
+
+    // Replaces this tick's actions, whatever they were on the tick before
+    pub fn set_input(&mut self, input: &TickInput) {
+        let bit = |bits: u8, i: usize| bits & (1 << i) != 0;
+        self.actions.reset_all();
+        // Pressing sets just_pressed, so held actions have it cleared straight after
+        for (i, action) in Action::TICKED.into_iter().enumerate() {
+            if bit(input.pressed, i) && !bit(input.just_pressed, i) {
+                self.actions.press(action);
+            }
+        }
+        self.actions.clear();
+        for (i, action) in Action::TICKED.into_iter().enumerate() {
+            if bit(input.just_pressed, i) {
+                self.actions.press(action);
+            }
+        }
+        self.values.0 = Action::TICKED.into_iter().zip(input.values).collect();
+    }
+}
+
+// Actions are up to date once this has run, in PreUpdate for each frame and in
+// FixedPreUpdate for each tick
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ActionSet;
+
+// Raw device state that bindings are checked against
+#[derive(SystemParam)]
+struct RawInput<'w> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    gamepads: Res<'w, Gamepads>,
+    gamepad_buttons: Res<'w, ButtonInput<GamepadButton>>,
+    gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+}
+
+impl RawInput<'_> {
+    // Gamepad bindings work on any connected gamepad
+    fn pressed(&self, binding: &Binding) -> bool {
+        match *binding {
+            Binding::Key(key) => self.keys.pressed(key),
+            Binding::Mouse(button) => self.mouse.pressed(button),
+            Binding::GamepadButton(button) => self.gamepads.iter().any(|gamepad| {
+                self.gamepad_buttons
+                    .pressed(GamepadButton::new(gamepad, button))
+            }),
+            Binding::GamepadAxis(axis, sign) => self.gamepads.iter().any(|gamepad| {
+                self.gamepad_axes
+                    .get(GamepadAxis::new(gamepad, axis))
+                    .is_some_and(|value| value * sign > AXIS_THRESHOLD)
+            }),
+        }
+    }
+
+    fn value(&self, binding: &Binding, deadzone: f32) -> f32 {
+        let Binding::GamepadAxis(axis, sign) = *binding else {
+            return if self.pressed(binding) { 1. } else { 0. };
+        };
+        self.gamepads
+            .iter()
+            .filter_map(|gamepad| self.gamepad_axes.get(GamepadAxis::new(gamepad, axis)))
+            .map(|value| {
+                let value = value * sign;
+                if value <= deadzone {
+                    0.
+                } else {
+                    ((value - deadzone) / (1. - deadzone)).min(1.)
+                }
+            })
+            .fold(0., f32::max)
+    }
+
+    // Whatever was pressed this frame, for rebinding
+    fn any_just_pressed(&self) -> Option<Binding> {
+        if let Some(key) = self.keys.get_just_pressed().next() {
+            return Some(Binding::Key(*key));
+        }
+        if let Some(button) = self.mouse.get_just_pressed().next() {
+            return Some(Binding::Mouse(*button));
+        }
+        if let Some(button) = self.gamepad_buttons.get_just_pressed().next() {
+            return Some(Binding::GamepadButton(button.button_type));
+        }
+        let sticks = [
+            GamepadAxisType::LeftStickX,
+            GamepadAxisType::LeftStickY,
+            GamepadAxisType::RightStickX,
+            GamepadAxisType::RightStickY,
+        ];
+        self.gamepads.iter().find_map(|gamepad| {
+            sticks.into_iter().find_map(|axis| {
+                let value = self.gamepad_axes.get(GamepadAxis::new(gamepad, axis))?;
+                (value.abs() > AXIS_THRESHOLD).then(|| Binding::GamepadAxis(axis, value.signum()))
+            })
+        })
+    }
+}
+
+// Lists every action's bindings and lets the player change them. While it's
+// open, gameplay sees no actions at all.
+#[derive(Resource, Default)]
+pub struct ControlsMenu {
+    open: bool,
+    selected: usize,
+    // Waiting for the new binding for the selected action
+    listening: bool,
+    // Game time was running when the menu opened, so it resumes on closing
+    paused_time: bool,
+}
+
+impl ControlsMenu {
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+#[derive(Component)]
+struct ControlsMenuScreen;
+
+// Other menus run after this, so a press that opens the controls menu isn't
+// also taken as the first press inside it
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ControlsMenuSet;
+
+const CONTROLS_KEY: KeyCode = KeyCode::F1;
+
+pub struct ActionPlugin;
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_bindings())
+            .init_resource::<ButtonInput<Action>>()
+            .init_resource::<ActionValues>()
+            .init_resource::<TickActions>()
+            .init_resource::<ControlsMenu>()
+            .add_systems(
+                PreUpdate,
+                (log_gamepad_connections, update_actions.in_set(ActionSet)).after(InputSystem),
+            )
+            .add_systems(FixedPreUpdate, tick_actions.in_set(ActionSet))
+            .add_systems(
+                Update,
+                (controls_menu, pause_for_controls_menu, show_controls_menu)
+                    .chain()
+                    .in_set(ControlsMenuSet),
+            );
+    }
+}
+
+fn load_bindings() -> InputBindings {
+    let Ok(text) = fs::read_to_string(BINDINGS_PATH) else {
+        return InputBindings::default();
+    };
+    parse_bindings(&text).unwrap_or_else(|e| {
+        warn!(
+            "Ignoring unreadable bindings {}, controls are back to the defaults: {}",
+            BINDINGS_PATH, e
+        );
+        InputBindings::default()
+    })
+}
+
+// Bindings used to be saved as just the map of actions, before the deadzone
+// setting was added. Those files still load, with the default deadzone, and
+// actions added since a file was saved get their default bindings.
+fn parse_bindings(text: &str) -> Result<InputBindings, ron::error::SpannedError> {
+    let mut bindings = ron::from_str(text).or_else(|e| {
+        ron::from_str::<BTreeMap<Action, Vec<Binding>>>(text)
+            .map(|actions| InputBindings {
+                actions,
+                ..default()
+            })
+            .map_err(|_| e)
+    })?;
+    let mut defaults = InputBindings::default();
+    for action in Action::ALL {
+        if let Some(default) = defaults.remove(&action) {
+            bindings.entry(action).or_insert(default);
+        }
+    }
+    Ok(bindings)
+}
+
+fn save_bindings(bindings: &InputBindings) {
+    let result = ron::ser::to_string_pretty(bindings, default())
+        .map_err(|e| e.to_string())
+        .and_then(|text| fs::write(BINDINGS_PATH, text).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        warn!("Could not save bindings to {}: {}", BINDINGS_PATH, e);
+    }
+}
+
+// Gamepads can come and go at any time. Bindings apply to whichever are
+// connected, so there's nothing to do beyond letting the player know.
+fn log_gamepad_connections(mut connection_events: EventReader<GamepadConnectionEvent>) {
+    for event in connection_events.read() {
+        match &event.connection {
+            GamepadConnection::Connected(info) => {
+                info!("Gamepad {} connected: {}", event.gamepad.id, info.name)
+            }
+            GamepadConnection::Disconnected => {
+                info!("Gamepad {} disconnected", event.gamepad.id)
+            }
+        }
+    }
+}
+
+fn update_actions(
+    raw_input: RawInput,
+    bindings: Res<InputBindings>,
+    controls_menu: Res<ControlsMenu>,
+    mut actions: ResMut<ButtonInput<Action>>,
+    mut action_values: ResMut<ActionValues>,
+    mut tick_actions: ResMut<TickActions>,
+    time: Res<Time<Virtual>>,
+) {
+    actions.clear();
+    action_values.0.clear();
+    if controls_menu.open {
+        actions.release_all();
+        return;
+    }
+
+    for action in Action::ALL {
+        let action_bindings = bindings.get(&action).map_or(&[][..], Vec::as_slice);
+        if action_bindings.iter().any(|b| raw_input.pressed(b)) {
+            actions.press(action);
+        } else {
+            actions.release(action);
+        }
+
+        let value = action_bindings
+            .iter()
+            .map(|b| raw_input.value(b, bindings.deadzone))
+            .fold(0., f32::max);
+        action_values.0.insert(action, value);
+    }
+    // Presses in a pause menu shouldn't carry over to the first tick after it
+    if !time.is_paused() {
+        tick_actions
+            .pressed_since_tick
+            .extend(actions.get_just_pressed());
+    }
+}
+
+fn tick_actions(
+    actions: Res<ButtonInput<Action>>,
+    action_values: Res<ActionValues>,
+    mut tick_actions: ResMut<TickActions>,
+) {
+    let pressed_since_tick = std::mem::take(&mut tick_actions.pressed_since_tick);
+    tick_actions.clear();
+    for action in Action::ALL {
+        if actions.pressed(action) || pressed_since_tick.contains(&action) {
+            tick_actions.press(action);
+        } else {
+            tick_actions.release(action);
+        }
+    }
+    tick_actions.values = action_values.clone();
+}
+
+// Up and down pick an action, Enter (or South) waits for a new binding to
+// replace the ones from that device, Backspace (or West) puts back the
+// defaults, and Escape cancels or closes
+fn controls_menu(
+    raw_input: RawInput,
+    mut controls_menu: ResMut<ControlsMenu>,
+    mut bindings: ResMut<InputBindings>,
+) {
+    let keys = &raw_input.keys;
+    let pad = |button| {
+        raw_input.gamepads.iter().any(|gamepad| {
+            raw_input
+                .gamepad_buttons
+                .just_pressed(GamepadButton::new(gamepad, button))
+        })
+    };
+
+    if !controls_menu.open {
+        if keys.just_pressed(CONTROLS_KEY) {
+            controls_menu.open = true;
+        }
+        return;
+    }
+
+    let action = Action::ALL[controls_menu.selected];
+    if controls_menu.listening {
+        if keys.just_pressed(KeyCode::Escape) {
+            controls_menu.listening = false;
+        } else if let Some(binding) = raw_input.any_just_pressed() {
+            let action_bindings = bindings.entry(action).or_default();
+            action_bindings.retain(|b| b.is_gamepad() != binding.is_gamepad());
+            action_bindings.push(binding);
+            controls_menu.listening = false;
+            save_bindings(&bindings);
+        }
+        return;
+    }
+
+    if keys.just_pressed(CONTROLS_KEY) || keys.just_pressed(KeyCode::Escape) {
+        controls_menu.open = false;
+    } else if keys.just_pressed(KeyCode::ArrowUp) || pad(GamepadButtonType::DPadUp) {
+        controls_menu.selected = controls_menu.selected.saturating_sub(1);
+    } else if keys.just_pressed(KeyCode::ArrowDown) || pad(GamepadButtonType::DPadDown) {
+        controls_menu.selected = (controls_menu.selected + 1).min(Action::ALL.len() - 1);
+    } else if keys.just_pressed(KeyCode::Enter) || pad(GamepadButtonType::South) {
+        controls_menu.listening = true;
+    } else if keys.just_pressed(KeyCode::Backspace) || pad(GamepadButtonType::West) {
+        let defaults = InputBindings::default();
+        bindings.insert(action, defaults[&action].clone());
+        save_bindings(&bindings);
+    }
+}
+
+// Opening the controls menu in the middle of a level stops the game until it
+// closes, while a pause menu underneath keeps the game stopped after
+fn pause_for_controls_menu(
+    mut controls_menu: ResMut<ControlsMenu>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if controls_menu.open && !time.is_paused() {
+        time.pause();
+        controls_menu.paused_time = true;
+    } else if !controls_menu.open && controls_menu.paused_time {
+        time.unpause();
+        controls_menu.paused_time = false;
+    }
+}
+
+fn show_controls_menu(
+    mut commands: Commands,
+    controls_menu: Res<ControlsMenu>,
+    bindings: Res<InputBindings>,
+    screen: Query<Entity, With<ControlsMenuScreen>>,
+) {
+    if !controls_menu.is_changed() && !bindings.is_changed() {
+        return;
+    }
+    for entity in screen.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !controls_menu.open {
+        return;
+    }
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(10.),
+                    ..default()
+                },
+                background_color: Color::srgba(0., 0., 0., 0.8).into(),
+                z_index: ZIndex::Global(100),
+                ..default()
+            },
+            ControlsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Controls",
+                TextStyle {
+                    font_size: 40.,
+                    ..default()
+                },
+            ));
+            for (i, action) in Action::ALL.into_iter().enumerate() {
+                let selected = i == controls_menu.selected;
+                let bound = if selected && controls_menu.listening {
+                    "press a key or button...".to_string()
+                } else {
+                    bindings
+                        .get(&action)
+                        .map(|bindings| {
+                            bindings
+                                .iter()
+                                .map(Binding::to_string)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        })
+                        .unwrap_or_default()
+                };
+                parent.spawn(TextBundle::from_section(
+                    format!(
+                        "{}{}: {}",
+                        if selected { "> " } else { "  " },
+                        action.label(),
+                        bound
+                    ),
+                    TextStyle {
+                        font_size: 24.,
+                        ..default()
+                    },
+                ));
+            }
+            parent.spawn(TextBundle::from_section(
+                "Up/Down to choose, Enter to rebind, Backspace for defaults, Esc to close",
+                TextStyle {
+                    font_size: 16.,
+                    ..default()
+                },
+            ));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::input::{
+        gamepad::{GamepadAxisChangedEvent, GamepadButtonChangedEvent, GamepadEvent, GamepadInfo},
+        keyboard::{Key, KeyboardInput},
+        ButtonState, InputPlugin,
+    };
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    // Everything the action layer needs, with no window or real devices
+    fn headless_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, InputPlugin, ActionPlugin))
+            // Ignore any bindings saved on this machine
+            .insert_resource(InputBindings::default())
+            // Fixed ticks only run when a test asks for one
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO));
+        app
+    }
+
+    fn tick(app: &mut App) -> &TickActions {
+        app.world_mut().run_schedule(FixedPreUpdate);
+        app.world().resource::<TickActions>()
+    }
+
+    fn send(app: &mut App, event: impl Into<GamepadEvent>) {
+        app.world_mut().send_event(event.into());
+    }
+
+    fn connect(app: &mut App, id: usize) -> Gamepad {
+        let gamepad = Gamepad::new(id);
+        let info = GamepadInfo {
+            name: "Test pad".to_string(),
+        };
+        send(
+            app,
+            GamepadConnectionEvent::new(gamepad, GamepadConnection::Connected(info)),
+        );
+        app.update();
+        gamepad
+    }
+
+    fn stick_x(app: &mut App, gamepad: Gamepad, value: f32) {
+        send(
+            app,
+            GamepadAxisChangedEvent::new(gamepad, GamepadAxisType::LeftStickX, value),
+        );
+        app.update();
+    }
+
+    fn value(app: &App, action: Action) -> f32 {
+        app.world().resource::<ActionValues>().get(action)
+    }
+
+    fn pressed(app: &App, action: Action) -> bool {
+        app.world()
+            .resource::<ButtonInput<Action>>()
+            .pressed(action)
+    }
+
+    #[test]
+    fn stick_inside_deadzone_does_nothing() {
+        let mut app = headless_app();
+        let gamepad = connect(&mut app, 0);
+
+        stick_x(&mut app, gamepad, STICK_DEADZONE * 0.9);
+        assert_eq!(value(&app, Action::MoveRight), 0.);
+        assert!(!pressed(&app, Action::MoveRight));
+    }
+
+    #[test]
+    fn stick_scales_from_edge_of_deadzone() {
+        let mut app = headless_app();
+        app.world_mut().resource_mut::<InputBindings>().deadzone = 0.2;
+        let gamepad = connect(&mut app, 0);
+
+        stick_x(&mut app, gamepad, 0.6);
+        assert!((value(&app, Action::MoveRight) - 0.5).abs() < 1e-6);
+        assert_eq!(value(&app, Action::MoveLeft), 0.);
+
+        stick_x(&mut app, gamepad, -1.);
+        assert_eq!(value(&app, Action::MoveLeft), 1.);
+        assert_eq!(value(&app, Action::MoveRight), 0.);
+        assert!(pressed(&app, Action::MoveLeft));
+    }
+
+    #[test]
+    fn dpad_and_face_buttons_trigger_actions() {
+        let mut app = headless_app();
+        let gamepad = connect(&mut app, 0);
+
+        send(
+            &mut app,
+            GamepadButtonChangedEvent::new(gamepad, GamepadButtonType::DPadLeft, 1.),
+        );
+        send(
+            &mut app,
+            GamepadButtonChangedEvent::new(gamepad, GamepadButtonType::South, 1.),
+        );
+        app.update();
+
+        let actions = app.world().resource::<ButtonInput<Action>>();
+        assert!(actions.pressed(Action::MoveLeft));
+        assert!(actions.just_pressed(Action::Jump));
+        assert_eq!(value(&app, Action::MoveLeft), 1.);
+
+        app.update();
+        let actions = app.world().resource::<ButtonInput<Action>>();
+        assert!(actions.pressed(Action::Jump));
+        assert!(!actions.just_pressed(Action::Jump));
+    }
+
+    #[test]
+    fn unplugging_releases_and_replugging_works() {
+        let mut app = headless_app();
+        let gamepad = connect(&mut app, 0);
+        stick_x(&mut app, gamepad, 1.);
+        assert!(pressed(&app, Action::MoveRight));
+
+        send(
+            &mut app,
+            GamepadConnectionEvent::new(gamepad, GamepadConnection::Disconnected),
+        );
+        app.update();
+        assert!(!pressed(&app, Action::MoveRight));
+        assert_eq!(value(&app, Action::MoveRight), 0.);
+
+        let gamepad = connect(&mut app, 1);
+        stick_x(&mut app, gamepad, 1.);
+        assert!(pressed(&app, Action::MoveRight));
+        assert_eq!(value(&app, Action::MoveRight), 1.);
+    }
+
+    #[test]
+    fn controls_menu_hides_actions_from_gameplay() {
+        let mut app = headless_app();
+        let gamepad = connect(&mut app, 0);
+        app.world_mut().resource_mut::<ControlsMenu>().open = true;
+
+        stick_x(&mut app, gamepad, 1.);
+        assert!(!pressed(&app, Action::MoveRight));
+        assert_eq!(value(&app, Action::MoveRight), 0.);
+    }
+
+    fn press_controls_key(app: &mut App) {
+        for state in [ButtonState::Pressed, ButtonState::Released] {
+            app.world_mut().send_event(KeyboardInput {
+                key_code: CONTROLS_KEY,
+                logical_key: Key::F1,
+                state,
+                window: Entity::PLACEHOLDER,
+            });
+            app.update();
+        }
+    }
+
+    #[test]
+    fn controls_menu_pauses_the_game() {
+        let mut app = headless_app();
+        press_controls_key(&mut app);
+        assert!(app.world().resource::<ControlsMenu>().is_open());
+        assert!(app.world().resource::<Time<Virtual>>().is_paused());
+        press_controls_key(&mut app);
+        assert!(!app.world().resource::<Time<Virtual>>().is_paused());
+
+        // Already paused by a menu, which is still open after
+        app.world_mut().resource_mut::<Time<Virtual>>().pause();
+        press_controls_key(&mut app);
+        press_controls_key(&mut app);
+        assert!(!app.world().resource::<ControlsMenu>().is_open());
+        assert!(app.world().resource::<Time<Virtual>>().is_paused());
+    }
+
+    #[test]
+    fn presses_reach_the_next_tick_once() {
+        let mut app = headless_app();
+        let gamepad = connect(&mut app, 0);
+        let south =
+            |value| GamepadButtonChangedEvent::new(gamepad, GamepadButtonType::South, value);
+
+        // Tapped and let go between ticks
+        send(&mut app, south(1.));
+        app.update();
+        send(&mut app, south(0.));
+        app.update();
+        assert!(tick(&mut app).just_pressed(Action::Jump));
+        assert!(!tick(&mut app).pressed(Action::Jump));
+
+        // Held over two ticks in the same frame
+        send(&mut app, south(1.));
+        app.update();
+        assert!(tick(&mut app).just_pressed(Action::Jump));
+        let actions = tick(&mut app);
+        assert!(actions.pressed(Action::Jump));
+        assert!(!actions.just_pressed(Action::Jump));
+    }
+
+    #[test]
+    fn tick_input_round_trips() {
+        let mut tick_actions = TickActions::default();
+        tick_actions.press(Action::MoveRight);
+        tick_actions.clear();
+        tick_actions.press(Action::Jump);
+        tick_actions.values.0.insert(Action::MoveRight, 0.75);
+        let input = tick_actions.input();
+
+        // Starting from a different state makes no difference
+        let mut replayed = TickActions::default();
+        replayed.press(Action::MoveLeft);
+        replayed.set_input(&input);
+        assert!(replayed.pressed(Action::MoveRight));
+        assert!(!replayed.just_pressed(Action::MoveRight));
+        assert!(replayed.just_pressed(Action::Jump));
+        assert!(!replayed.pressed(Action::MoveLeft));
+        assert_eq!(replayed.values.get(Action::MoveRight), 0.75);
+        assert_eq!(replayed.input(), input);
+    }
+
+    #[test]
+    fn old_bindings_file_still_loads() {
+        let old = "{MoveLeft: [Key(KeyJ)], Jump: [GamepadButton(North)]}";
+        let bindings = parse_bindings(old).unwrap();
+        assert_eq!(bindings.deadzone, STICK_DEADZONE);
+        assert_eq!(bindings[&Action::MoveLeft], [Binding::Key(KeyCode::KeyJ)]);
+        assert_eq!(
+            bindings[&Action::Jump],
+            [Binding::GamepadButton(GamepadButtonType::North)]
+        );
+        // Saved before there were menu actions
+        assert_eq!(
+            bindings[&Action::MenuUp],
+            InputBindings::default()[&Action::MenuUp]
+        );
+
+        let current = ron::to_string(&bindings).unwrap();
+        assert_eq!(parse_bindings(&current).unwrap().actions, bindings.actions);
+        assert!(parse_bindings("nonsense").is_err());
+    }
+}
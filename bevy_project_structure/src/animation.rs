@@ -0,0 +1,220 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopMode {
+    #[default]
+    Loop,
+    // Stops on the last frame
+    Once,
+    // Plays forwards then backwards, over and over
+    PingPong,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AnimationClip {
+    // First and last atlas index, inclusive
+    pub frames: (usize, usize),
+    // Seconds each frame is shown. Frames past the end of the list use the last value.
+    pub durations: Vec<f32>,
+    #[serde(default)]
+    pub mode: LoopMode,
+}
+
+impl AnimationClip {
+    fn len(&self) -> usize {
+        self.frames.1.saturating_sub(self.frames.0) + 1
+    }
+
+    fn duration(&self, frame: usize) -> f32 {
+        self.durations
+            .get(frame)
+            .or(self.durations.last())
+            .copied()
+            .unwrap_or(f32::INFINITY)
+    }
+}
+
+// Named clips for one sprite sheet, for example:
+//
+//   {
+//       "idle": (frames: (0, 0), durations: [0.5]),
+//       "walk": (frames: (0, 3), durations: [0.2], mode: Loop),
+//   }
+#[derive(Asset, TypePath, Deserialize, Debug, Deref)]
+#[serde(transparent)]
+pub struct AnimationClips(HashMap<String, AnimationClip>);
+
+#[derive(Default)]
+pub struct AnimationClipsLoader;
+
+#[derive(Error, Debug)]
+pub enum AnimationClipsLoaderError {
+    #[error("Could not read animation clips: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse animation clips: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for AnimationClipsLoader {
+    type Asset = AnimationClips;
+    type Settings = ();
+    type Error = AnimationClipsLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["clips.ron"]
+    }
+}
+
+// Plays clips on the entity's TextureAtlas. Whatever drives the entity (player
+// input, enemy AI) decides which clip should be showing and calls `play`.
+#[derive(Component)]
+pub struct Animator {
+    pub clips: Handle<AnimationClips>,
+    clip: String,
+    frame: usize,
+    elapsed: f32,
+    reverse: bool,
+    finished: bool,
+}
+
+impl Animator {
+    pub fn new(clips: Handle<AnimationClips>, clip: &str) -> Self {
+        Self {
+            clips,
+            clip: clip.to_string(),
+            frame: 0,
+            elapsed: 0.,
+            reverse: false,
+            finished: false,
+        }
+    }
+
+    pub fn clip(&self) -> &str {
+        &self.clip
+    }
+
+    // Index into the clip of the frame showing
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    // Switches clip, starting from its first frame. Asking for the clip
+    // that's already playing carries on where it is.
+    pub fn play(&mut self, clip: &str) {
+        if self.clip != clip {
+            *self = Self::new(self.clips.clone(), clip);
+        }
+    }
+
+    // Whether a Once clip has reached its last frame
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    fn advance(&mut self, clip: &AnimationClip) {
+        let last = clip.len() - 1;
+        match clip.mode {
+            LoopMode::Loop => {
+                self.frame = if self.frame >= last {
+                    0
+                } else {
+                    self.frame + 1
+                }
+            }
+            LoopMode::Once if self.frame >= last => self.finished = true,
+            LoopMode::Once => self.frame += 1,
+            LoopMode::PingPong if last == 0 => {}
+            LoopMode::PingPong => {
+                if self.frame >= last {
+                    self.reverse = true;
+                } else if self.frame == 0 {
+                    self.reverse = false;
+                }
+                if self.reverse {
+                    self.frame -= 1;
+                } else {
+                    self.frame += 1;
+                }
+            }
+        }
+    }
+}
+
+// Which way a sprite faces. Sheets are drawn facing right.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Facing {
+    Left,
+    #[default]
+    Right,
+}
+
+// Systems that pick clips run before this. Clips advance on fixed ticks along
+// with the rest of gameplay, so frames line up with what the player is doing.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AnimationSet;
+
+pub struct AnimationPlugin;
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AnimationClips>()
+            .init_asset_loader::<AnimationClipsLoader>()
+            .add_systems(FixedUpdate, (play_clips, face_sprites).in_set(AnimationSet));
+    }
+}
+
+fn play_clips(
+    time: Res<Time>,
+    clip_sets: Res<Assets<AnimationClips>>,
+    texture_atlases: Res<Assets<TextureAtlasLayout>>,
+    mut animated: Query<(&mut Animator, &mut TextureAtlas)>,
+) {
+    for (mut animator, mut texture_atlas) in animated.iter_mut() {
+        let Some(clip) = clip_sets
+            .get(&animator.clips)
+            .and_then(|clips| clips.get(&animator.clip))
+            .cloned()
+        else {
+            continue;
+        };
+
+        animator.elapsed += time.delta_seconds();
+        while !animator.finished && animator.elapsed >= clip.duration(animator.frame) {
+            let duration = clip.duration(animator.frame);
+            if duration <= 0. {
+                break;
+            }
+            animator.elapsed -= duration;
+            animator.advance(&clip);
+        }
+
+        let index = clip.frames.0 + animator.frame.min(clip.len() - 1);
+        // The sheet may have shrunk under the clips during hot reloading
+        let frame_count = texture_atlases
+            .get(&texture_atlas.layout)
+            .map_or(usize::MAX, |layout| layout.len().max(1));
+        texture_atlas.index = index % frame_count;
+    }
+}
+
+fn face_sprites(mut sprites: Query<(&Facing, &mut Sprite), Changed<Facing>>) {
+    for (facing, mut sprite) in sprites.iter_mut() {
+        sprite.flip_x = *facing == Facing::Left;
+    }
+}
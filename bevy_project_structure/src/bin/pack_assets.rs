@@ -0,0 +1,84 @@
+// Builds assets.pack from the assets folder for shipping builds:
+//   cargo run --bin pack_assets -- [assets dir] [output file]
+use std::{
+    env, fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+#[path = "../pack_format.rs"]
+mod pack_format;
+
+use pack_format::{data_offset, pack_bytes, write_index, PackEntry};
+
+const DEFAULT_ASSETS_DIR: &str = "assets";
+const DEFAULT_PACK_PATH: &str = "assets.pack";
+
+fn main() -> io::Result<()> {
+    let mut args = env::args().skip(1);
+    let assets_dir = args.next().unwrap_or(String::from(DEFAULT_ASSETS_DIR));
+    let pack_path = args.next().unwrap_or(String::from(DEFAULT_PACK_PATH));
+
+    let mut files = Vec::new();
+    collect_files(Path::new(&assets_dir), Path::new(""), &mut files)?;
+    files.sort();
+
+    let mut entries = Vec::new();
+    let mut blobs = Vec::new();
+    for path in files {
+        let bytes = fs::read(Path::new(&assets_dir).join(&path))?;
+        let (stored, compressed) = pack_bytes(&bytes)?;
+        println!(
+            "{}: {} -> {} bytes{}",
+            path,
+            bytes.len(),
+            stored.len(),
+            if compressed { " (deflated)" } else { "" }
+        );
+
+        entries.push(PackEntry {
+            path,
+            offset: 0,
+            stored_len: stored.len() as u64,
+            size: bytes.len() as u64,
+            crc32: crc32fast::hash(&bytes),
+            compressed,
+        });
+        blobs.push(stored);
+    }
+
+    let mut offset = data_offset(&entries);
+    for entry in entries.iter_mut() {
+        entry.offset = offset;
+        offset += entry.stored_len;
+    }
+
+    let mut writer = BufWriter::new(fs::File::create(&pack_path)?);
+    write_index(&mut writer, &entries)?;
+    for blob in blobs {
+        writer.write_all(&blob)?;
+    }
+    writer.flush()?;
+
+    println!("Wrote {} assets to {}", entries.len(), pack_path);
+    Ok(())
+}
+
+// Asset paths are stored relative to the assets folder with '/' separators,
+// the same way AssetServer::load is given them
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(dir))? {
+        let entry = entry?;
+        let path = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            let parts: Vec<_> = path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            files.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,488 @@
+use bevy::prelude::*;
+
+use crate::{
+    interpolation::InterpolationSet,
+    level::LevelBounds,
+    level_data::{Properties, PropertyValue},
+    settings::Settings,
+    GameState, CAMERA_DEADZONE, CAMERA_LOOK_AHEAD, CAMERA_MAX_LOOK_AHEAD, CAMERA_SMOOTH_TIME,
+    FLASH_TIME, MAX_SHAKE_ANGLE, MAX_SHAKE_OFFSET, PUNCH_DECAY, SHAKE_FREQUENCY, TRAUMA_DECAY,
+    WIN_H, WIN_W,
+};
+
+// Properties on a camera zone object in the map editor
+const LOCK_PROPERTY: &str = "lock";
+const ZOOM_PROPERTY: &str = "zoom";
+
+// Follows whatever has CameraTarget. The target can move around a deadzone in
+// the middle of the view without the camera moving, the camera leads the way
+// the target is heading, and it eases into place rather than snapping.
+#[derive(Component, Debug, Clone)]
+pub struct CameraController {
+    // Size of the deadzone, in world units
+    pub deadzone: Vec2,
+    // Roughly how long it takes to catch up, in seconds
+    pub smooth_time: f32,
+    // How many seconds ahead of a moving target to look, up to max_look_ahead
+    pub look_ahead: f32,
+    pub max_look_ahead: Vec2,
+    // World area the view is kept inside, if there is one
+    pub bounds: Option<Rect>,
+    // How much of the world is in view, with 2 showing twice as much as normal
+    pub zoom: f32,
+    // Jump straight to the target next frame instead of easing there
+    pub snap: bool,
+    // Centre of the deadzone
+    focus: Vec2,
+    // Where following puts the camera, before any shake
+    position: Vec2,
+    scale: f32,
+    last_target: Option<Vec2>,
+    velocity: Vec2,
+    zoom_velocity: f32,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            deadzone: CAMERA_DEADZONE,
+            smooth_time: CAMERA_SMOOTH_TIME,
+            look_ahead: CAMERA_LOOK_AHEAD,
+            max_look_ahead: CAMERA_MAX_LOOK_AHEAD,
+            bounds: None,
+            zoom: 1.,
+            snap: true,
+            focus: Vec2::ZERO,
+            position: Vec2::ZERO,
+            scale: 1.,
+            last_target: None,
+            velocity: Vec2::ZERO,
+            zoom_velocity: 0.,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct CameraTarget;
+
+// Part of a level where the camera behaves differently: either locked on the
+// middle of the zone, or kept inside it. Either way it can also zoom.
+#[derive(Component, Debug, Clone)]
+pub struct CameraZone {
+    pub area: Rect,
+    pub lock: bool,
+    pub zoom: f32,
+}
+
+impl CameraZone {
+    pub fn new(area: Rect, properties: &Properties) -> Self {
+        let zoom = match properties.get(ZOOM_PROPERTY) {
+            Some(PropertyValue::Float(zoom)) => *zoom as f32,
+            Some(PropertyValue::Int(zoom)) => *zoom as f32,
+            _ => 1.,
+        };
+        Self {
+            area,
+            lock: properties.get(LOCK_PROPERTY) == Some(&PropertyValue::Bool(true)),
+            zoom,
+        }
+    }
+}
+
+// Shakes the camera. Trauma adds up to at most 1 and wears off over time, and
+// the shake grows with the square of it, so small knocks stay subtle.
+#[derive(Event)]
+pub struct CameraShake {
+    pub trauma: f32,
+}
+
+// Briefly zooms in by `amount` (0.1 is 10%), easing back out
+#[derive(Event)]
+pub struct CameraPunchZoom {
+    pub amount: f32,
+}
+
+// Fills the screen with `color`, fading out over FLASH_TIME
+#[derive(Event)]
+pub struct CameraFlash {
+    pub color: Color,
+}
+
+// Effects on top of following, advanced on fixed ticks so a replay of the same
+// input shakes the same way
+#[derive(Resource, Default)]
+struct CameraEffects {
+    trauma: f32,
+    punch: f32,
+    flash: f32,
+    flash_color: Color,
+    // Seconds of play the shake noise has moved through
+    elapsed: f32,
+}
+
+// Covers the screen while a flash fades
+#[derive(Component)]
+struct FlashOverlay;
+
+// Follows the player, and shakes, punches and flashes when gameplay asks it to.
+// Shake noise comes from `shake_seed`.
+pub struct CameraPlugin {
+    pub shake_seed: u64,
+}
+
+#[derive(Resource, Deref)]
+struct ShakeSeed(u64);
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CameraShake>()
+            .add_event::<CameraPunchZoom>()
+            .add_event::<CameraFlash>()
+            .insert_resource(ShakeSeed(self.shake_seed))
+            .init_resource::<CameraEffects>()
+            .add_systems(Startup, spawn_flash_overlay)
+            .add_systems(
+                FixedPostUpdate,
+                update_effects.run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Update,
+                (
+                    set_camera_bounds.run_if(resource_exists_and_changed::<LevelBounds>),
+                    follow_target,
+                    apply_effects,
+                )
+                    .chain()
+                    .after(InterpolationSet)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnExit(GameState::Playing), reset_camera);
+    }
+}
+
+fn set_camera_bounds(level_bounds: Res<LevelBounds>, mut cameras: Query<&mut CameraController>) {
+    for mut controller in cameras.iter_mut() {
+        controller.bounds = Some(**level_bounds);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn follow_target(
+    time: Res<Time>,
+    target: Query<&Transform, With<CameraTarget>>,
+    zones: Query<(Entity, &CameraZone, Option<&Name>)>,
+    mut cameras: Query<
+        (
+            &mut CameraController,
+            &mut Transform,
+            &mut OrthographicProjection,
+        ),
+        Without<CameraTarget>,
+    >,
+    mut current_zone: Local<Option<Entity>>,
+) {
+    let Ok(target) = target.get_single() else {
+        return;
+    };
+    let target = target.translation.truncate();
+    let deltat = time.delta_seconds();
+
+    // The smallest zone the target is in
+    let zone = zones
+        .iter()
+        .filter(|(_, zone, _)| zone.area.contains(target))
+        .min_by(|(_, a, _), (_, b, _)| {
+            let area = |zone: &CameraZone| zone.area.width() * zone.area.height();
+            area(a).total_cmp(&area(b))
+        });
+    let zone_entity = zone.map(|(entity, _, _)| entity);
+    if zone_entity != *current_zone {
+        if let Some((_, _, Some(name))) = zone {
+            info!("Camera entering zone {}", name);
+        }
+        *current_zone = zone_entity;
+    }
+    let zone = zone.map(|(_, zone, _)| zone);
+
+    for (mut controller, mut transform, mut projection) in cameras.iter_mut() {
+        if controller.snap {
+            controller.focus = target;
+        }
+        // Keep the target inside the deadzone
+        let half_deadzone = controller.deadzone / 2.;
+        controller.focus = controller
+            .focus
+            .clamp(target - half_deadzone, target + half_deadzone);
+
+        let velocity = match controller.last_target {
+            Some(last_target) if deltat > 0. && !controller.snap => (target - last_target) / deltat,
+            _ => Vec2::ZERO,
+        };
+        controller.last_target = Some(target);
+        let look_ahead = (velocity * controller.look_ahead)
+            .clamp(-controller.max_look_ahead, controller.max_look_ahead);
+
+        let (mut wanted, zoom, bounds) = match zone {
+            Some(zone) if zone.lock => (zone.area.center(), zone.zoom, controller.bounds),
+            Some(zone) => (controller.focus + look_ahead, zone.zoom, Some(zone.area)),
+            None => (
+                controller.focus + look_ahead,
+                controller.zoom,
+                controller.bounds,
+            ),
+        };
+        if let Some(bounds) = bounds {
+            wanted = keep_in_view(wanted, zoom, bounds);
+        }
+
+        let current = controller.position;
+        let current_scale = controller.scale;
+        let (position, scale) = if controller.snap {
+            controller.snap = false;
+            controller.velocity = Vec2::ZERO;
+            controller.zoom_velocity = 0.;
+            (wanted, zoom)
+        } else {
+            let smooth_time = controller.smooth_time;
+            let CameraController {
+                velocity,
+                zoom_velocity,
+                ..
+            } = &mut *controller;
+            (
+                smooth_damp(current, wanted, velocity, smooth_time, deltat),
+                smooth_damp_f32(current_scale, zoom, zoom_velocity, smooth_time, deltat),
+            )
+        };
+        controller.position = position;
+        controller.scale = scale;
+        transform.translation = position.extend(transform.translation.z);
+        projection.scale = scale;
+    }
+}
+
+fn spawn_flash_overlay(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            background_color: Color::NONE.into(),
+            // Over the rest of the UI
+            z_index: ZIndex::Global(i32::MAX),
+            ..default()
+        },
+        FlashOverlay,
+    ));
+}
+
+fn update_effects(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut effects: ResMut<CameraEffects>,
+    mut shakes: EventReader<CameraShake>,
+    mut punches: EventReader<CameraPunchZoom>,
+    mut flashes: EventReader<CameraFlash>,
+) {
+    let deltat = time.delta_seconds();
+    effects.elapsed += deltat;
+    effects.trauma = (effects.trauma - TRAUMA_DECAY * deltat).max(0.);
+    effects.punch = (effects.punch - PUNCH_DECAY * deltat).max(0.);
+    effects.flash = (effects.flash - deltat / FLASH_TIME).max(0.);
+
+    for shake in shakes.read() {
+        effects.trauma = (effects.trauma + shake.trauma * settings.screen_shake).min(1.);
+    }
+    for punch in punches.read() {
+        effects.punch = effects.punch.max(punch.amount * settings.screen_shake);
+    }
+    for flash in flashes.read() {
+        if settings.flashes {
+            effects.flash = 1.;
+            effects.flash_color = flash.color;
+        }
+    }
+}
+
+fn apply_effects(
+    time: Res<Time<Fixed>>,
+    shake_seed: Res<ShakeSeed>,
+    effects: Res<CameraEffects>,
+    mut cameras: Query<(
+        &CameraController,
+        &mut Transform,
+        &mut OrthographicProjection,
+    )>,
+    mut overlay: Query<&mut BackgroundColor, With<FlashOverlay>>,
+) {
+    // Between ticks, like the rest of what's drawn
+    let t = (effects.elapsed + time.overstep().as_secs_f32()) * SHAKE_FREQUENCY;
+    let shake = effects.trauma * effects.trauma;
+    let offset =
+        Vec2::new(noise(**shake_seed, 0, t), noise(**shake_seed, 1, t)) * MAX_SHAKE_OFFSET * shake;
+    let angle = noise(**shake_seed, 2, t) * MAX_SHAKE_ANGLE * shake;
+
+    for (controller, mut transform, mut projection) in cameras.iter_mut() {
+        transform.translation = (controller.position + offset).extend(transform.translation.z);
+        transform.rotation = Quat::from_rotation_z(angle);
+        projection.scale = controller.scale * (1. - effects.punch);
+    }
+
+    if let Ok(mut overlay) = overlay.get_single_mut() {
+        *overlay = effects
+            .flash_color
+            .with_alpha(effects.flash_color.alpha() * effects.flash)
+            .into();
+    }
+}
+
+// Smooth noise from -1 to 1 that changes about once per unit of `t`. Each
+// channel is unrelated to the others, and the same seed always gives the same
+// values.
+fn noise(seed: u64, channel: u64, t: f32) -> f32 {
+    let i = t.floor();
+    let f = t - i;
+    let i = i as i64 as u64;
+    let a = lattice(seed, channel, i);
+    let b = lattice(seed, channel, i.wrapping_add(1));
+    // Smoothstep, so it doesn't change direction in a sharp corner
+    a + (b - a) * f * f * (3. - 2. * f)
+}
+
+fn lattice(seed: u64, channel: u64, i: u64) -> f32 {
+    // SplitMix64
+    let mut z =
+        seed ^ channel.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ i.wrapping_mul(0xd1b5_4a32_d192_ed03);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 23) as f32 - 1.
+}
+
+// Where the camera can be nearest to `centre` with the whole view inside
+// `bounds`, or in the middle of them if the view is bigger
+fn keep_in_view(centre: Vec2, zoom: f32, bounds: Rect) -> Vec2 {
+    let half_view = Vec2::new(WIN_W, WIN_H) * zoom / 2.;
+    let min = bounds.min + half_view;
+    let max = bounds.max - half_view;
+    Vec2::new(
+        if min.x <= max.x {
+            centre.x.clamp(min.x, max.x)
+        } else {
+            bounds.center().x
+        },
+        if min.y <= max.y {
+            centre.y.clamp(min.y, max.y)
+        } else {
+            bounds.center().y
+        },
+    )
+}
+
+// Critically damped spring towards `target`, so it gets there as fast as it
+// can without overshooting. `velocity` carries over between calls.
+fn smooth_damp(
+    current: Vec2,
+    target: Vec2,
+    velocity: &mut Vec2,
+    smooth_time: f32,
+    deltat: f32,
+) -> Vec2 {
+    let omega = 2. / smooth_time.max(f32::EPSILON);
+    let x = omega * deltat;
+    // Close approximation of e^-x
+    let decay = 1. / (1. + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (*velocity + omega * change) * deltat;
+    *velocity = (*velocity - omega * temp) * decay;
+    target + (change + temp) * decay
+}
+
+fn smooth_damp_f32(
+    current: f32,
+    target: f32,
+    velocity: &mut f32,
+    smooth_time: f32,
+    deltat: f32,
+) -> f32 {
+    let mut velocity2 = Vec2::new(*velocity, 0.);
+    let result = smooth_damp(
+        Vec2::new(current, 0.),
+        Vec2::new(target, 0.),
+        &mut velocity2,
+        smooth_time,
+        deltat,
+    );
+    *velocity = velocity2.x;
+    result.x
+}
+
+fn reset_camera(
+    mut cameras: Query<(
+        &mut CameraController,
+        &mut Transform,
+        &mut OrthographicProjection,
+    )>,
+    mut effects: ResMut<CameraEffects>,
+    mut overlay: Query<&mut BackgroundColor, With<FlashOverlay>>,
+) {
+    for (mut controller, mut transform, mut projection) in cameras.iter_mut() {
+        transform.translation.x = 0.;
+        transform.translation.y = 0.;
+        transform.rotation = Quat::IDENTITY;
+        projection.scale = 1.;
+        controller.snap = true;
+        controller.last_target = None;
+    }
+    *effects = CameraEffects::default();
+    for mut overlay in overlay.iter_mut() {
+        *overlay = Color::NONE.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 1. / 60.;
+
+    #[test]
+    fn smoothing_settles_without_overshoot() {
+        let mut position = Vec2::ZERO;
+        let mut velocity = Vec2::ZERO;
+        let target = Vec2::new(500., -200.);
+        for _ in 0..120 {
+            position = smooth_damp(position, target, &mut velocity, 0.2, STEP);
+            assert!(position.x <= target.x && position.y >= target.y);
+        }
+        assert!(position.distance(target) < 1.);
+    }
+
+    #[test]
+    fn shake_depends_only_on_seed() {
+        for i in 0..100 {
+            let t = i as f32 * 0.37;
+            let value = noise(7, 0, t);
+            assert!((-1. ..=1.).contains(&value));
+            assert_eq!(value, noise(7, 0, t));
+        }
+        let differs =
+            |other: u64| (0..100).any(|i| noise(7, 0, i as f32) != noise(other, 0, i as f32));
+        assert!(differs(8));
+    }
+
+    #[test]
+    fn view_stays_inside_bounds() {
+        let bounds = Rect::new(-WIN_W / 2., -WIN_H / 2., WIN_W * 2., WIN_H / 2.);
+        assert_eq!(keep_in_view(Vec2::new(-1000., 50.), 1., bounds), Vec2::ZERO);
+        assert_eq!(
+            keep_in_view(Vec2::new(5000., 0.), 1., bounds),
+            Vec2::new(WIN_W * 1.5, 0.)
+        );
+        // Zoomed out too far to fit, so it sits in the middle
+        assert_eq!(keep_in_view(Vec2::ZERO, 2., bounds).y, bounds.center().y);
+    }
+}
@@ -0,0 +1,169 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::{
+    loading::{despawn_with, AssetGroups, GameAssets, LoadingSet},
+    replay::Playback,
+    GameState, LEVEL_COMPLETE_TIME, SAVE_PATH,
+};
+
+// Sent when the player touches the goal of the current level
+#[derive(Event, Default)]
+pub struct GoalReached;
+
+// Index into the manifest's list of levels
+#[derive(Resource, Deref, DerefMut)]
+pub struct CurrentLevel(pub usize);
+
+// Saved between runs
+#[derive(Resource, Serialize, Deserialize, Default, Debug)]
+pub struct Progress {
+    // Furthest level the player has reached
+    pub unlocked: usize,
+}
+
+#[derive(Component)]
+struct LevelCompleteScreen;
+
+#[derive(Resource, Deref, DerefMut)]
+struct LevelCompleteTimer(Timer);
+
+pub struct CampaignPlugin;
+impl Plugin for CampaignPlugin {
+    fn build(&self, app: &mut App) {
+        let progress = load_progress();
+        app.insert_resource(CurrentLevel(progress.unlocked))
+            .insert_resource(progress)
+            .add_event::<GoalReached>()
+            .add_systems(
+                Update,
+                stream_current_level
+                    .after(LoadingSet::Manifest)
+                    .before(LoadingSet::Stream)
+                    .run_if(resource_exists::<GameAssets>),
+            )
+            .add_systems(
+                Update,
+                goal_reached
+                    .run_if(on_event::<GoalReached>())
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnEnter(GameState::LevelComplete), setup_level_complete)
+            .add_systems(
+                Update,
+                level_complete_countdown.run_if(in_state(GameState::LevelComplete)),
+            )
+            .add_systems(
+                OnExit(GameState::LevelComplete),
+                despawn_with::<LevelCompleteScreen>,
+            );
+    }
+}
+
+fn load_progress() -> Progress {
+    let Ok(text) = fs::read_to_string(SAVE_PATH) else {
+        return Progress::default();
+    };
+    ron::from_str(&text).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable save file {}: {}", SAVE_PATH, e);
+        Progress::default()
+    })
+}
+
+fn save_progress(progress: &Progress) {
+    let result = ron::ser::to_string_pretty(progress, default())
+        .map_err(|e| e.to_string())
+        .and_then(|text| fs::write(SAVE_PATH, text).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        warn!("Could not save progress to {}: {}", SAVE_PATH, e);
+    }
+}
+
+// While playing, get the next level's assets in ahead of time
+fn stream_current_level(
+    state: Res<State<GameState>>,
+    game_assets: Res<GameAssets>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut asset_groups: ResMut<AssetGroups>,
+) {
+    let campaign = game_assets.campaign();
+    if campaign.is_empty() {
+        return;
+    }
+    // The save may come from a build with more levels
+    if **current_level >= campaign.len() {
+        **current_level = campaign.len() - 1;
+    }
+
+    let index = if *state.get() == GameState::Playing {
+        (**current_level + 1).min(campaign.len() - 1)
+    } else {
+        **current_level
+    };
+    asset_groups.stream(&campaign[index]);
+}
+
+fn goal_reached(
+    game_assets: Res<GameAssets>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut progress: ResMut<Progress>,
+    mut next_state: ResMut<NextState<GameState>>,
+    playback: Option<Res<Playback>>,
+) {
+    if **current_level + 1 >= game_assets.campaign().len() {
+        next_state.set(GameState::Win);
+        return;
+    }
+
+    **current_level += 1;
+    // Watching a recording doesn't unlock anything
+    if **current_level > progress.unlocked && playback.is_none() {
+        progress.unlocked = **current_level;
+        save_progress(&progress);
+    }
+    next_state.set(GameState::LevelComplete);
+}
+
+fn setup_level_complete(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    current_level: Res<CurrentLevel>,
+) {
+    let levels = game_assets.campaign().len();
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_sections([
+                TextSection::new(
+                    "Level complete!\n",
+                    TextStyle {
+                        font_size: 60.,
+                        ..default()
+                    },
+                ),
+                TextSection::new(
+                    format!("Next up: level {} of {}", **current_level + 1, levels),
+                    TextStyle::default(),
+                ),
+            ])
+            .with_justify(JustifyText::Center),
+            ..default()
+        },
+        LevelCompleteScreen,
+    ));
+
+    commands.insert_resource(LevelCompleteTimer(Timer::from_seconds(
+        LEVEL_COMPLETE_TIME,
+        TimerMode::Once,
+    )));
+}
+
+fn level_complete_countdown(
+    time: Res<Time>,
+    mut timer: ResMut<LevelCompleteTimer>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if timer.tick(time.delta()).just_finished() {
+        next_state.set(GameState::Loading);
+    }
+}
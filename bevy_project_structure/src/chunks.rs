@@ -0,0 +1,211 @@
+use bevy::{prelude::*, transform::TransformSystem, utils::HashMap};
+use std::ops::Range;
+
+use crate::{
+    level::{
+        tile_to_world, ActiveLevel, Background, BackgroundImage, Brick, BrickSheets, LevelSetup,
+        PropertyComponents,
+    },
+    level_data::LevelData,
+    loading::AssetsReloaded,
+    GameState, CHUNK_MARGIN, CHUNK_WIDTH, TILE_SIZE, WIN_W,
+};
+
+// Tiles and background panels currently spawned, by chunk and panel index
+#[derive(Resource, Default)]
+pub struct LoadedChunks {
+    tiles: HashMap<usize, Vec<Entity>>,
+    backgrounds: HashMap<usize, Entity>,
+}
+
+impl LoadedChunks {
+    pub fn tile_count(&self) -> usize {
+        self.tiles.values().map(Vec::len).sum()
+    }
+}
+
+// Hidden tile sprites from chunks that scrolled away, ready to be reused
+#[derive(Resource, Default, Deref, DerefMut)]
+struct TilePool(Vec<Entity>);
+
+// Only the part of the level near the camera has entities. Long levels are cut
+// into chunks of CHUNK_WIDTH columns, which are filled in as they come within
+// CHUNK_MARGIN chunks of the screen and handed back to the pool once they're
+// further away than that.
+pub struct ChunkPlugin;
+impl Plugin for ChunkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadedChunks>()
+            .init_resource::<TilePool>()
+            .add_systems(
+                Update,
+                clear_chunks
+                    .after(LevelSetup)
+                    .run_if(on_event::<AssetsReloaded>())
+                    .run_if(in_state(GameState::Playing)),
+            )
+            // After anything that moves the camera, and before the new sprites need transforms
+            .add_systems(
+                PostUpdate,
+                stream_chunks
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnExit(GameState::Playing), clear_chunks);
+    }
+}
+
+fn clear_chunks(
+    mut commands: Commands,
+    mut loaded_chunks: ResMut<LoadedChunks>,
+    mut tile_pool: ResMut<TilePool>,
+) {
+    let LoadedChunks { tiles, backgrounds } = &mut *loaded_chunks;
+    let tiles = tiles.drain().flat_map(|(_, tiles)| tiles);
+    let backgrounds = backgrounds.drain().map(|(_, background)| background);
+    for entity in tiles.chain(backgrounds).chain(tile_pool.drain(..)) {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn stream_chunks(
+    mut commands: Commands,
+    camera: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+    levels: Res<Assets<LevelData>>,
+    active_level: Res<ActiveLevel>,
+    brick_sheets: Res<BrickSheets>,
+    background_image: Res<BackgroundImage>,
+    property_components: Res<PropertyComponents>,
+    mut loaded_chunks: ResMut<LoadedChunks>,
+    mut tile_pool: ResMut<TilePool>,
+) {
+    let Some(level) = levels.get(&**active_level) else {
+        return;
+    };
+    let (ct, projection) = camera.single();
+    // Never narrower than the window, so a zoom that hasn't been applied yet can't leave holes
+    let half_width = (projection.area.width() * ct.scale.x / 2.).max(WIN_W / 2.);
+    let view = (ct.translation.x - half_width)..(ct.translation.x + half_width);
+
+    // Tiles, a chunk at a time
+    let chunk_count = level.width.div_ceil(CHUNK_WIDTH);
+    let chunk_width = CHUNK_WIDTH as f32 * TILE_SIZE;
+    let wanted = index_range(
+        (view.start + WIN_W / 2.) / chunk_width,
+        (view.end + WIN_W / 2.) / chunk_width,
+        CHUNK_MARGIN,
+        chunk_count,
+    );
+
+    let stale: Vec<usize> = loaded_chunks
+        .tiles
+        .keys()
+        .filter(|chunk| !wanted.contains(chunk))
+        .copied()
+        .collect();
+    for chunk in stale {
+        for entity in loaded_chunks.tiles.remove(&chunk).unwrap() {
+            // Strip back to a bare sprite, dropping Brick and anything added from properties
+            commands
+                .entity(entity)
+                .retain::<(SpriteBundle, TextureAtlas)>()
+                .insert(Visibility::Hidden);
+            tile_pool.push(entity);
+        }
+    }
+
+    for chunk in wanted {
+        if loaded_chunks.tiles.contains_key(&chunk) {
+            continue;
+        }
+        let columns = chunk * CHUNK_WIDTH..((chunk + 1) * CHUNK_WIDTH).min(level.width);
+        let mut tiles = Vec::new();
+
+        for (i, layer) in level.layers.iter().enumerate() {
+            let brick_sheet = &brick_sheets[&layer.tileset];
+            // Keep later layers in front
+            let z = 1. + i as f32 * 0.01;
+
+            for y in 0..level.height {
+                for x in columns.clone() {
+                    let Some(index) = level.tile(i, x, y) else {
+                        continue;
+                    };
+                    let t = tile_to_world(UVec2::new(x as u32, y as u32).as_vec2());
+                    let texture = brick_sheet.0.clone();
+                    let transform = Transform::from_xyz(t.x, t.y, z);
+                    let texture_atlas = TextureAtlas {
+                        layout: brick_sheet.1.clone(),
+                        index,
+                    };
+
+                    let mut brick = match tile_pool.pop() {
+                        Some(entity) => {
+                            let mut brick = commands.entity(entity);
+                            brick.insert((
+                                texture,
+                                transform,
+                                texture_atlas,
+                                Visibility::Inherited,
+                                Brick,
+                            ));
+                            brick
+                        }
+                        None => commands.spawn((
+                            SpriteBundle {
+                                texture,
+                                transform,
+                                ..default()
+                            },
+                            texture_atlas,
+                            Brick,
+                        )),
+                    };
+                    for (name, value) in layer.properties.iter() {
+                        if let Some(add_component) = property_components.get(name) {
+                            add_component(&mut brick, value);
+                        }
+                    }
+                    tiles.push(brick.id());
+                }
+            }
+        }
+        loaded_chunks.tiles.insert(chunk, tiles);
+    }
+
+    // Background panels are a window wide and centred on multiples of WIN_W
+    let level_len = level.width as f32 * TILE_SIZE;
+    let wanted = index_range(
+        (view.start + WIN_W / 2.) / WIN_W,
+        (view.end + WIN_W / 2.) / WIN_W,
+        0,
+        (level_len / WIN_W).ceil() as usize,
+    );
+    loaded_chunks.backgrounds.retain(|panel, entity| {
+        let keep = wanted.contains(panel);
+        if !keep {
+            commands.entity(*entity).despawn();
+        }
+        keep
+    });
+    for panel in wanted {
+        loaded_chunks.backgrounds.entry(panel).or_insert_with(|| {
+            commands
+                .spawn(SpriteBundle {
+                    texture: background_image.0.clone(),
+                    transform: Transform::from_xyz(panel as f32 * WIN_W, 0., 0.),
+                    ..default()
+                })
+                .insert(Background)
+                .id()
+        });
+    }
+}
+
+// Indices covering from..to (in index units), widened by margin and clamped to 0..count
+fn index_range(from: f32, to: f32, margin: usize, count: usize) -> Range<usize> {
+    let start = (from.floor().max(0.) as usize).saturating_sub(margin);
+    let end = (to.floor().max(0.) as usize + 1 + margin).min(count);
+    start.min(end)..end
+}
@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+
+use crate::{
+    level_data::LevelData,
+    loading::{AssetsReloaded, GameAssets},
+    manifest::{AssetManifest, AtlasGrid},
+    GameState,
+};
+
+// Dev builds only (--features dev): follows edits to files under assets/ while playing
+pub struct HotReloadPlugin;
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (reload_manifest, resize_atlas_layouts, reload_level_data)
+                .run_if(in_state(GameState::Playing))
+                .run_if(resource_exists::<GameAssets>),
+        );
+    }
+}
+
+fn reload_manifest(
+    mut manifest_events: EventReader<AssetEvent<AssetManifest>>,
+    manifests: Res<Assets<AssetManifest>>,
+    asset_server: Res<AssetServer>,
+    mut game_assets: ResMut<GameAssets>,
+    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut reloaded: EventWriter<AssetsReloaded>,
+) {
+    for event in manifest_events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+        let Some(manifest) = manifests.get(*id) else {
+            continue;
+        };
+
+        info!("Asset manifest changed");
+        if game_assets.apply_manifest(manifest, &asset_server, &mut texture_atlases) {
+            reloaded.send_default();
+        }
+    }
+}
+
+// A sheet that grew or shrank keeps its tile size, so work out the new grid from the image
+fn resize_atlas_layouts(
+    mut image_events: EventReader<AssetEvent<Image>>,
+    images: Res<Assets<Image>>,
+    game_assets: Res<GameAssets>,
+    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut reloaded: EventWriter<AssetsReloaded>,
+) {
+    for event in image_events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+        let Some((key, entry)) = game_assets.entry(id.untyped()) else {
+            continue;
+        };
+        info!("Image changed: {}", entry.path);
+
+        let (Some(grid), Some(image)) = (entry.atlas, images.get(*id)) else {
+            reloaded.send_default();
+            continue;
+        };
+        let size = image.size() / grid.tile_size;
+        let resized = AtlasGrid {
+            columns: size.x.max(1),
+            rows: size.y.max(1),
+            ..grid
+        };
+        let current = game_assets
+            .layout(key)
+            .and_then(|layout| texture_atlases.get(&layout))
+            .map(|layout| layout.size / grid.tile_size);
+
+        if current != Some(UVec2::new(resized.columns, resized.rows)) {
+            info!(
+                "Resizing {:?} atlas to {}x{}",
+                key, resized.columns, resized.rows
+            );
+            game_assets.rebuild_layout(key, resized, &mut texture_atlases);
+        }
+        reloaded.send_default();
+    }
+}
+
+fn reload_level_data(
+    mut level_events: EventReader<AssetEvent<LevelData>>,
+    game_assets: Res<GameAssets>,
+    mut reloaded: EventWriter<AssetsReloaded>,
+) {
+    for event in level_events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+        if let Some((_, entry)) = game_assets.entry(id.untyped()) {
+            info!("Level changed: {}", entry.path);
+            reloaded.send_default();
+        }
+    }
+}
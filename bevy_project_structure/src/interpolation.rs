@@ -0,0 +1,65 @@
+use bevy::prelude::*;
+
+use crate::FIXED_HZ;
+
+// Moved by gameplay on fixed ticks, but drawn part way between the last two
+// ticks so motion stays smooth at any refresh rate. During the fixed schedule
+// Transform holds the simulated position, and the drawn one the rest of the time.
+#[derive(Component)]
+pub struct Interpolated {
+    previous: Vec3,
+    current: Vec3,
+}
+
+impl Interpolated {
+    pub fn new(translation: Vec3) -> Self {
+        Self {
+            previous: translation,
+            current: translation,
+        }
+    }
+
+    // Jump straight to a new position instead of sliding there over a tick
+    pub fn snap(&mut self, translation: Vec3) {
+        self.previous = translation;
+        self.current = translation;
+    }
+}
+
+// Anything following an interpolated entity (like the camera) runs after this
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InterpolationSet;
+
+pub struct InterpolationPlugin;
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(FIXED_HZ))
+            .add_systems(FixedFirst, restore_translations)
+            .add_systems(FixedLast, store_translations)
+            .add_systems(Update, interpolate_translations.in_set(InterpolationSet));
+    }
+}
+
+// Put back the simulated position before each tick
+fn restore_translations(mut interpolated: Query<(&mut Interpolated, &mut Transform)>) {
+    for (mut interpolated, mut transform) in interpolated.iter_mut() {
+        interpolated.previous = interpolated.current;
+        transform.translation = interpolated.current;
+    }
+}
+
+fn store_translations(mut interpolated: Query<(&mut Interpolated, &Transform)>) {
+    for (mut interpolated, transform) in interpolated.iter_mut() {
+        interpolated.current = transform.translation;
+    }
+}
+
+fn interpolate_translations(
+    time: Res<Time<Fixed>>,
+    mut interpolated: Query<(&Interpolated, &mut Transform)>,
+) {
+    let t = time.overstep_fraction();
+    for (interpolated, mut transform) in interpolated.iter_mut() {
+        transform.translation = interpolated.previous.lerp(interpolated.current, t);
+    }
+}
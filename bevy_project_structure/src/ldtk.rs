@@ -0,0 +1,355 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, ReadAssetBytesError},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::level_data::{
+    LevelData, LevelObject, Properties, PropertyValue, TileLayer, SOLID_PROPERTY,
+};
+
+const SOLID_LAYER: &str = "Solid";
+
+// Loads LDtk project files. The first level is the asset itself and every
+// level is also available by its identifier as a labelled asset, for example
+// "world.ldtk#Level_2".
+//
+// Each tileset's identifier is the manifest key of the atlas its tiles are
+// drawn from, and tile ids are atlas indices. Entities use their identifier as
+// their kind, and their fields as properties. Each level needs a string field
+// "background" naming the background image. Layers can't have fields, so tile
+// layers whose identifier starts with "Solid" are the solid ones.
+#[derive(Default)]
+pub struct LdtkLoader;
+
+#[derive(Error, Debug)]
+pub enum LdtkLoaderError {
+    #[error("Could not read project: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not read external level: {0}")]
+    Level(#[from] ReadAssetBytesError),
+    #[error("Could not parse project: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid level {0:?}, {1}")]
+    Invalid(String, String),
+}
+
+impl AssetLoader for LdtkLoader {
+    type Asset = LevelData;
+    type Settings = ();
+    type Error = LdtkLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let project: LdtkProject = serde_json::from_slice(&bytes)?;
+
+        let tilesets: HashMap<i64, String> = project
+            .defs
+            .tilesets
+            .into_iter()
+            .map(|tileset| (tileset.uid, tileset.identifier))
+            .collect();
+
+        let mut first = None;
+        for mut level in project.levels {
+            // Projects saved with "separate level files" keep the layers elsewhere
+            if level.layer_instances.is_none() {
+                if let Some(rel_path) = level.external_rel_path.as_ref() {
+                    let path = load_context
+                        .asset_path()
+                        .resolve_embed(rel_path)
+                        .map_err(|e| {
+                            LdtkLoaderError::Invalid(level.identifier.clone(), e.to_string())
+                        })?;
+                    let bytes = load_context.read_asset_bytes(path).await?;
+                    level = serde_json::from_slice(&bytes)?;
+                }
+            }
+
+            let identifier = level.identifier.clone();
+            let level_data = level_data(level, &tilesets)
+                .map_err(|message| LdtkLoaderError::Invalid(identifier.clone(), message))?;
+            if first.is_none() {
+                first = Some(level_data.clone());
+            }
+            load_context.add_labeled_asset(identifier, level_data);
+        }
+
+        first.ok_or_else(|| LdtkLoaderError::Invalid(String::new(), "no levels".to_string()))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ldtk"]
+    }
+}
+
+fn level_data(level: LdtkLevel, tilesets: &HashMap<i64, String>) -> Result<LevelData, String> {
+    let layer_instances = level.layer_instances.unwrap_or_default();
+
+    // All tile layers have to share a grid, which is the one the level is built on
+    let grid_size = layer_instances
+        .iter()
+        .find(|layer| layer.tileset_def_uid.is_some())
+        .map(|layer| layer.grid_size)
+        .ok_or("no tile layers")?;
+    if grid_size <= 0 {
+        return Err("grid size must be positive".to_string());
+    }
+    if level.px_wid < 0 || level.px_hei < 0 {
+        return Err("level size must not be negative".to_string());
+    }
+    let width = (level.px_wid / grid_size) as usize;
+    let height = (level.px_hei / grid_size) as usize;
+    let level_height = level.px_hei as f32;
+
+    let mut layers = Vec::new();
+    let mut objects = Vec::new();
+    // LDtk lists layers top first
+    for layer in layer_instances.into_iter().rev() {
+        for entity in layer.entity_instances.iter() {
+            let size = Vec2::new(entity.width, entity.height);
+            let pivot = Vec2::from(entity.pivot);
+            let centre = Vec2::from(entity.px) - pivot * size + size / 2.;
+            let centre = Vec2::new(centre.x, level_height - centre.y);
+
+            objects.push(LevelObject {
+                kind: entity.identifier.clone(),
+                name: entity.identifier.clone(),
+                position: centre / layer.grid_size as f32 - Vec2::splat(0.5),
+                size: size / layer.grid_size as f32,
+                properties: field_properties(&entity.field_instances),
+            });
+        }
+
+        let Some(tileset_uid) = layer.tileset_def_uid else {
+            continue;
+        };
+        if layer.grid_size != grid_size {
+            return Err(format!(
+                "layer {:?} has a {}px grid, expected {}px",
+                layer.identifier, layer.grid_size, grid_size
+            ));
+        }
+        let tileset = tilesets
+            .get(&tileset_uid)
+            .ok_or_else(|| format!("layer {:?} has an unknown tileset", layer.identifier))?;
+
+        let mut tiles = vec![None; width * height];
+        for tile in layer.grid_tiles.iter().chain(layer.auto_layer_tiles.iter()) {
+            let x = (tile.px[0] / grid_size) as usize;
+            let row = (tile.px[1] / grid_size) as usize;
+            if x >= width || row >= height {
+                continue;
+            }
+            tiles[(height - 1 - row) * width + x] = Some(tile.t);
+        }
+        let mut properties = Properties::default();
+        if layer.identifier.starts_with(SOLID_LAYER) {
+            properties.insert(SOLID_PROPERTY.to_string(), PropertyValue::Bool(true));
+        }
+        layers.push(TileLayer {
+            tileset: tileset.clone(),
+            tiles,
+            properties,
+        });
+    }
+
+    LevelData::from_map(
+        width,
+        height,
+        layers,
+        objects,
+        &field_properties(&level.field_instances),
+    )
+}
+
+fn field_properties(fields: &[LdtkField]) -> Properties {
+    fields
+        .iter()
+        .filter_map(|field| {
+            let value = match (field.kind.as_str(), &field.value) {
+                ("Bool", serde_json::Value::Bool(b)) => PropertyValue::Bool(*b),
+                ("Int", serde_json::Value::Number(n)) => PropertyValue::Int(n.as_i64()?),
+                ("Float", serde_json::Value::Number(n)) => PropertyValue::Float(n.as_f64()?),
+                (_, serde_json::Value::String(s)) => PropertyValue::String(s.clone()),
+                // Unset fields, points, arrays and entity references
+                _ => return None,
+            };
+            Some((field.identifier.clone(), value))
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LdtkProject {
+    defs: LdtkDefs,
+    levels: Vec<LdtkLevel>,
+}
+
+#[derive(Deserialize)]
+struct LdtkDefs {
+    tilesets: Vec<LdtkTileset>,
+}
+
+#[derive(Deserialize)]
+struct LdtkTileset {
+    uid: i64,
+    identifier: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LdtkLevel {
+    identifier: String,
+    px_wid: i64,
+    px_hei: i64,
+    #[serde(default)]
+    field_instances: Vec<LdtkField>,
+    #[serde(default)]
+    layer_instances: Option<Vec<LdtkLayer>>,
+    #[serde(default)]
+    external_rel_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LdtkField {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__type")]
+    kind: String,
+    #[serde(rename = "__value")]
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LdtkLayer {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__gridSize")]
+    grid_size: i64,
+    #[serde(rename = "__tilesetDefUid")]
+    tileset_def_uid: Option<i64>,
+    #[serde(default)]
+    grid_tiles: Vec<LdtkTile>,
+    #[serde(default)]
+    auto_layer_tiles: Vec<LdtkTile>,
+    #[serde(default)]
+    entity_instances: Vec<LdtkEntity>,
+}
+
+#[derive(Deserialize)]
+struct LdtkTile {
+    px: [i64; 2],
+    t: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LdtkEntity {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__pivot")]
+    pivot: [f32; 2],
+    px: [f32; 2],
+    width: f32,
+    height: f32,
+    #[serde(default)]
+    field_instances: Vec<LdtkField>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 3x2 level with a solid floor of tiles 0, 1, 0, an empty layer behind it
+    // and a layer of entities.
+    // `grid` is the tile layer's grid size and `tileset` its tileset uid.
+    fn level(grid: i64, tileset: i64) -> Result<LevelData, String> {
+        let text = format!(
+            r#"{{
+                "defs": {{"tilesets": [{{"uid": 1, "identifier": "bricks"}}]}},
+                "levels": [{{
+                    "identifier": "Level_0", "pxWid": 300, "pxHei": 200,
+                    "fieldInstances": [
+                        {{"__identifier": "background", "__type": "String", "__value": "sky"}},
+                        {{"__identifier": "music", "__type": "String", "__value": null}}
+                    ],
+                    "layerInstances": [
+                        {{
+                            "__identifier": "Entities", "__gridSize": 100, "__tilesetDefUid": null,
+                            "entityInstances": [
+                                {{"__identifier": "Player", "__pivot": [0.5, 1], "px": [50, 100],
+                                    "width": 100, "height": 100}},
+                                {{"__identifier": "Goal", "__pivot": [0, 0], "px": [200, 0],
+                                    "width": 100, "height": 100,
+                                    "fieldInstances": [
+                                        {{"__identifier": "locked", "__type": "Bool", "__value": true}}
+                                    ]}}
+                            ]
+                        }},
+                        {{
+                            "__identifier": "Solid", "__gridSize": {grid}, "__tilesetDefUid": {tileset},
+                            "gridTiles": [{{"px": [0, 100], "t": 0}}, {{"px": [100, 100], "t": 1}}],
+                            "autoLayerTiles": [{{"px": [200, 100], "t": 0}}, {{"px": [900, 0], "t": 2}}]
+                        }},
+                        {{"__identifier": "Decoration", "__gridSize": 100, "__tilesetDefUid": 1}}
+                    ]
+                }}]
+            }}"#
+        );
+        let project: LdtkProject = serde_json::from_str(&text).unwrap();
+        let tilesets = project
+            .defs
+            .tilesets
+            .into_iter()
+            .map(|tileset| (tileset.uid, tileset.identifier))
+            .collect();
+        let level = project.levels.into_iter().next().unwrap();
+        level_data(level, &tilesets)
+    }
+
+    #[test]
+    fn reads_tiles_and_entities() {
+        let level = level(100, 1).unwrap();
+        assert_eq!((level.width, level.height), (3, 2));
+        // Bottom layer first
+        assert_eq!(level.layers.len(), 2);
+        assert!(!level.layers[0].is_solid() && level.layers[1].is_solid());
+        assert_eq!(level.layers[1].tileset, "bricks");
+        // Tiles outside the level are dropped
+        assert_eq!(
+            level.layers[1].tiles,
+            [Some(0), Some(1), Some(0), None, None, None]
+        );
+        assert_eq!(
+            (level.spawn, level.goal),
+            (Vec2::new(0., 1.), Vec2::new(2., 1.))
+        );
+        assert_eq!(level.background, "sky");
+        assert_eq!(level.music, None);
+    }
+
+    #[test]
+    fn bad_levels() {
+        assert_eq!(level(0, 1).unwrap_err(), "grid size must be positive");
+        assert_eq!(level(-100, 1).unwrap_err(), "grid size must be positive");
+        assert_eq!(
+            level(50, 1).unwrap_err(),
+            "layer \"Decoration\" has a 100px grid, expected 50px"
+        );
+        assert_eq!(
+            level(100, 7).unwrap_err(),
+            "layer \"Solid\" has an unknown tileset"
+        );
+    }
+}
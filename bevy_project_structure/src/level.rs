@@ -1,8 +1,13 @@
-use bevy::{asset::LoadState, prelude::*};
+use bevy::{ecs::system::EntityCommands, prelude::*, utils::HashMap};
 
 use crate::{
-    loading::{despawn_with, LoadingAssets},
-    GameState, LEVEL_LEN, TILE_SIZE, WIN_H, WIN_W,
+    camera::CameraZone,
+    campaign::CurrentLevel,
+    ldtk::LdtkLoader,
+    level_data::{LevelData, LevelDataLoader, PropertyValue},
+    loading::{despawn_with, AssetsReloaded, GameAssets},
+    tiled::TiledLoader,
+    GameState, TILE_SIZE, WIN_H, WIN_W,
 };
 
 #[derive(Component)]
@@ -11,95 +16,205 @@ pub struct Brick;
 #[derive(Component)]
 pub struct Background;
 
+#[derive(Component)]
+pub struct Goal;
+
+// Anything spawned from a level object
+#[derive(Component)]
+pub struct MapObject;
+
+#[derive(Component)]
+pub struct Enemy;
+
+// Sends the player back to the start on touching it, as if they'd fallen out
+// of the level. Covers one tile around its position.
+#[derive(Component)]
+pub struct Hazard;
+
 #[derive(Resource)]
-pub struct BackgroundImage(Handle<Image>);
-#[derive(Resource)]
-pub struct BrickSheet(Handle<Image>, Handle<TextureAtlasLayout>);
+pub struct BackgroundImage(pub Handle<Image>);
+#[derive(Clone)]
+pub struct BrickSheet(pub Handle<Image>, pub Handle<TextureAtlasLayout>);
+
+// Sheets for every tileset the active level uses, by manifest key
+#[derive(Resource, Deref)]
+pub struct BrickSheets(pub HashMap<String, BrickSheet>);
+
+// Adds the component for a custom property set in the map editor
+pub type PropertyComponent = fn(&mut EntityCommands, &PropertyValue);
+
+// Layers pass their properties on to each of their tiles. Collision reads the
+// "solid" property straight from the level, so it has no component.
+#[derive(Resource, Deref, DerefMut)]
+pub struct PropertyComponents(HashMap<String, PropertyComponent>);
+
+impl Default for PropertyComponents {
+    fn default() -> Self {
+        let mut property_components = Self(HashMap::new());
+        property_components.insert(HAZARD_PROPERTY.to_string(), |entity, value| {
+            if *value == PropertyValue::Bool(true) {
+                entity.insert(Hazard);
+            }
+        });
+        property_components
+    }
+}
+
+#[derive(Resource, Deref)]
+pub struct ActiveLevel(pub Handle<LevelData>);
+
+// World-space area covered by the level
+#[derive(Resource, Deref)]
+pub struct LevelBounds(pub Rect);
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LevelSetup;
+
+const GOAL_COLOR: Color = Color::srgb(1., 0.85, 0.2);
+const ENEMY_COLOR: Color = Color::srgb(0.8, 0.2, 0.2);
+const ENEMY_OBJECT: &str = "Enemy";
+const CAMERA_ZONE_OBJECT: &str = "CameraZone";
+pub const HAZARD_PROPERTY: &str = "hazard";
 
 pub struct LevelPlugin;
 impl Plugin for LevelPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, load_level)
-            .add_systems(OnEnter(GameState::Playing), setup_level)
+        app.init_asset::<LevelData>()
+            .init_asset_loader::<LevelDataLoader>()
+            .init_asset_loader::<TiledLoader>()
+            .init_asset_loader::<LdtkLoader>()
+            .init_resource::<PropertyComponents>()
+            .add_systems(
+                OnEnter(GameState::Playing),
+                (load_level, setup_level).chain().in_set(LevelSetup),
+            )
+            .add_systems(
+                Update,
+                (
+                    despawn_with::<Goal>,
+                    despawn_with::<MapObject>,
+                    load_level,
+                    setup_level,
+                )
+                    .chain()
+                    .in_set(LevelSetup)
+                    .run_if(on_event::<AssetsReloaded>())
+                    .run_if(in_state(GameState::Playing)),
+            )
             .add_systems(
                 OnExit(GameState::Playing),
-                (despawn_with::<Brick>, despawn_with::<Background>),
+                (despawn_with::<Goal>, despawn_with::<MapObject>),
             );
     }
 }
 
+// Centre of a tile, with tile (0, 0) in the bottom left corner of the window
+pub fn tile_to_world(tile: Vec2) -> Vec2 {
+    Vec2::new(-WIN_W / 2., -WIN_H / 2.) + (tile + Vec2::splat(0.5)) * TILE_SIZE
+}
+
+pub fn world_to_tile(world: Vec2) -> Vec2 {
+    (world - Vec2::new(-WIN_W / 2., -WIN_H / 2.)) / TILE_SIZE - Vec2::splat(0.5)
+}
+
 fn load_level(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
-    mut loading_assets: ResMut<LoadingAssets>,
+    game_assets: Res<GameAssets>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<LevelData>>,
 ) {
-    let bg_texture_handle = asset_server.load("small_bg.png");
+    // Checked against the manifest, along with the assets the level names, while loading
+    let checked = "level checked at load";
+    let level_handle = game_assets
+        .level(&game_assets.campaign()[**current_level])
+        .expect(checked);
+    let level = levels
+        .get(&level_handle)
+        .expect("Level should have loaded before Playing");
 
-    loading_assets
-        .0
-        .push((bg_texture_handle.clone().untyped(), LoadState::NotLoaded));
-    commands.insert_resource(BackgroundImage(bg_texture_handle));
-
-    let brick_sheet_handle: Handle<Image> = asset_server.load("bricks.png");
-    loading_assets
-        .0
-        .push((brick_sheet_handle.clone().untyped(), LoadState::NotLoaded));
+    let sheets = level
+        .layers
+        .iter()
+        .map(|layer| {
+            let sheet = BrickSheet(
+                game_assets.image(&layer.tileset).expect(checked),
+                game_assets.layout(&layer.tileset).expect(checked),
+            );
+            (layer.tileset.clone(), sheet)
+        })
+        .collect();
 
-    let brick_layout =
-        TextureAtlasLayout::from_grid(UVec2::splat(TILE_SIZE as u32), 4, 1, None, None);
-    let brick_layout_handle = texture_atlases.add(brick_layout);
+    if let Some(music) = level
+        .music
+        .as_ref()
+        .filter(|key| game_assets.audio(key).is_none())
+    {
+        warn!(
+            "Level music {:?} is not audio in the asset manifest, playing the usual music instead",
+            music
+        );
+    }
 
-    commands.insert_resource(BrickSheet(brick_sheet_handle, brick_layout_handle));
+    let background = game_assets.image(&level.background).expect(checked);
+    commands.insert_resource(BackgroundImage(background));
+    commands.insert_resource(BrickSheets(sheets));
+    commands.insert_resource(ActiveLevel(level_handle));
 }
 
 fn setup_level(
     mut commands: Commands,
-    texture_atlases: Res<Assets<TextureAtlasLayout>>,
-    background_image: Res<BackgroundImage>,
-    brick_sheet: Res<BrickSheet>,
+    levels: Res<Assets<LevelData>>,
+    active_level: Res<ActiveLevel>,
+    property_components: Res<PropertyComponents>,
 ) {
-    let mut x_offset = 0.;
-    while x_offset < LEVEL_LEN {
-        commands
-            .spawn(SpriteBundle {
-                texture: background_image.0.clone(),
-                transform: Transform::from_xyz(x_offset, 0., 0.),
-                ..default()
-            })
-            .insert(Background);
+    let level = levels.get(&**active_level).unwrap();
+    let level_len = level.width as f32 * TILE_SIZE;
 
-        x_offset += WIN_W;
-    }
+    let min = Vec2::new(-WIN_W / 2., -WIN_H / 2.);
+    let size = Vec2::new(level_len, (level.height as f32 * TILE_SIZE).max(WIN_H));
+    commands.insert_resource(LevelBounds(Rect::from_corners(min, min + size)));
+
+    for object in level.objects.iter() {
+        let position = tile_to_world(object.position);
+        let mut entity = commands.spawn((
+            SpatialBundle::from_transform(Transform::from_xyz(position.x, position.y, 2.)),
+            Name::new(object.name.clone()),
+            MapObject,
+        ));
 
-    let brick_layout = texture_atlases.get(&brick_sheet.1);
-    let brick_layout_len = brick_layout.unwrap().len();
-    let mut i = 0;
-    let mut t = Vec3::new(
-        -WIN_W / 2. + TILE_SIZE / 2.,
-        -WIN_H / 2. + TILE_SIZE / 2.,
-        1.,
-    );
-    while (i as f32) * TILE_SIZE < LEVEL_LEN {
-        commands
-            .spawn((
-                SpriteBundle {
-                    texture: brick_sheet.0.clone(),
-                    transform: Transform {
-                        translation: t,
-                        ..default()
-                    },
+        if object.kind == ENEMY_OBJECT {
+            entity.insert((
+                Sprite {
+                    color: ENEMY_COLOR,
+                    custom_size: Some(object.size.max(Vec2::ONE) * TILE_SIZE),
                     ..default()
                 },
-                TextureAtlas {
-                    layout: brick_sheet.1.clone(),
-                    index: i % brick_layout_len,
-                },
-                Brick,
-            ))
-            .insert(Brick);
-
-        i += 1;
-        t += Vec3::new(TILE_SIZE, 0., 0.);
+                Handle::<Image>::default(),
+                Enemy,
+            ));
+        }
+        if object.kind == CAMERA_ZONE_OBJECT {
+            let area = Rect::from_center_size(position, object.size * TILE_SIZE);
+            entity.insert(CameraZone::new(area, &object.properties));
+        }
+        for (name, value) in object.properties.iter() {
+            if let Some(add_component) = property_components.get(name) {
+                add_component(&mut entity, value);
+            }
+        }
     }
+
+    let goal = tile_to_world(level.goal);
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: GOAL_COLOR,
+                custom_size: Some(Vec2::new(TILE_SIZE / 4., TILE_SIZE)),
+                ..default()
+            },
+            transform: Transform::from_xyz(goal.x, goal.y, 2.),
+            ..default()
+        },
+        Goal,
+    ));
 }
@@ -0,0 +1,434 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::HashMap,
+};
+use std::fmt;
+use thiserror::Error;
+
+// Object kinds with a fixed meaning, every level needs exactly one of each
+pub const PLAYER_OBJECT: &str = "Player";
+pub const GOAL_OBJECT: &str = "Goal";
+// Layer property marking tiles the player can't pass through
+pub const SOLID_PROPERTY: &str = "solid";
+
+// A level laid out on the tile grid. Row 0 is the bottom of the level.
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct LevelData {
+    pub width: usize,
+    pub height: usize,
+    // Drawn in order, later layers on top
+    pub layers: Vec<TileLayer>,
+    // Everything placed in the level other than the player and goal
+    pub objects: Vec<LevelObject>,
+    // Positions are in tiles, and may fall between tiles in imported maps
+    pub spawn: Vec2,
+    pub goal: Vec2,
+    // Manifest key for the background image
+    pub background: String,
+    // Manifest key for music to play instead of the usual track
+    pub music: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TileLayer {
+    // Manifest key for the sheet the tiles are drawn from
+    pub tileset: String,
+    // Atlas index for each tile, row-major from the bottom row up
+    pub tiles: Vec<Option<usize>>,
+    pub properties: Properties,
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelObject {
+    pub kind: String,
+    pub name: String,
+    // Centre of the object, in tiles
+    pub position: Vec2,
+    pub size: Vec2,
+    pub properties: Properties,
+}
+
+// Custom properties set on layers and objects in the map editor
+pub type Properties = HashMap<String, PropertyValue>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl TileLayer {
+    pub fn is_solid(&self) -> bool {
+        self.properties.get(SOLID_PROPERTY) == Some(&PropertyValue::Bool(true))
+    }
+}
+
+impl LevelData {
+    pub fn tile(&self, layer: usize, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            self.layers[layer].tiles[y * self.width + x]
+        } else {
+            None
+        }
+    }
+
+    // Whether any solid layer has a tile here. Everything outside the level is open.
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 {
+            return false;
+        }
+        self.layers
+            .iter()
+            .enumerate()
+            .any(|(i, layer)| layer.is_solid() && self.tile(i, x as usize, y as usize).is_some())
+    }
+
+    // Used by the map importers, which place the player and goal as objects
+    pub fn from_map(
+        width: usize,
+        height: usize,
+        layers: Vec<TileLayer>,
+        mut objects: Vec<LevelObject>,
+        properties: &Properties,
+    ) -> Result<Self, String> {
+        let mut take = |kind: &str| {
+            let found: Vec<usize> = (0..objects.len())
+                .filter(|i| objects[*i].kind == kind)
+                .collect();
+            match found.as_slice() {
+                [index] => Ok(objects.remove(*index).position),
+                [] => Err(format!("no `{}` object", kind)),
+                _ => Err(format!("more than one `{}` object", kind)),
+            }
+        };
+        let spawn = take(PLAYER_OBJECT)?;
+        let goal = take(GOAL_OBJECT)?;
+
+        let background = match properties.get("background") {
+            Some(PropertyValue::String(key)) => key.clone(),
+            _ => return Err("no `background` string property on the map".to_string()),
+        };
+        let music = match properties.get("music") {
+            Some(PropertyValue::String(key)) => Some(key.clone()),
+            _ => None,
+        };
+
+        Ok(Self {
+            width,
+            height,
+            layers,
+            objects,
+            spawn,
+            goal,
+            background,
+            music,
+        })
+    }
+
+    // Plain text format, for example:
+    //
+    //   // comments start with two slashes, before the map
+    //   tileset = bricks
+    //   background = background
+    //   music = bg_music (optional)
+    //   tile # = 0
+    //   map:
+    //
+    //    P            G
+    //   ################
+    //
+    // Everything after "map:" is the tile grid, top row first. Spaces and '.' are
+    // empty, 'P' is the player spawn, 'G' is the goal, and any other character
+    // must be given an atlas index with a "tile" line. Every tile is solid.
+    pub fn parse(text: &str) -> Result<Self, LevelParseError> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+        let mut tileset = None;
+        let mut background = None;
+        let mut music = None;
+        let mut legend = HashMap::new();
+        let mut map_line = None;
+
+        for (line_no, line) in lines.by_ref() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with("//") {
+                continue;
+            }
+            // Columns count characters, so they match what an editor shows
+            let column = line.chars().count() - line.trim_start().chars().count() + 1;
+            if trimmed == "map:" {
+                map_line = Some(line_no);
+                break;
+            }
+
+            let Some((key, value)) = trimmed.split_once('=') else {
+                return Err(LevelParseError::new(
+                    line_no,
+                    column,
+                    "expected `key = value`, a `tile` line or `map:`",
+                ));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let after_eq = &line[line.find('=').unwrap() + 1..];
+            let value_column = line.chars().count() - after_eq.trim_start().chars().count() + 1;
+
+            match key.split_whitespace().collect::<Vec<_>>().as_slice() {
+                ["tileset"] => tileset = Some(value.to_string()),
+                ["background"] => background = Some(value.to_string()),
+                ["music"] => music = Some(value.to_string()),
+                ["tile", c] if c.chars().count() == 1 => {
+                    let c = c.chars().next().unwrap();
+                    if matches!(c, ' ' | '.' | 'P' | 'G') {
+                        return Err(LevelParseError::new(
+                            line_no,
+                            column,
+                            format!("`{}` is reserved and can't be used as a tile", c),
+                        ));
+                    }
+                    let index = value.parse::<usize>().map_err(|_| {
+                        LevelParseError::new(
+                            line_no,
+                            value_column,
+                            format!("expected an atlas index, found `{}`", value),
+                        )
+                    })?;
+                    legend.insert(c, index);
+                }
+                ["tile", ..] => {
+                    return Err(LevelParseError::new(
+                        line_no,
+                        column,
+                        "expected `tile <character> = <atlas index>`",
+                    ))
+                }
+                _ => {
+                    return Err(LevelParseError::new(
+                        line_no,
+                        column,
+                        format!("unknown setting `{}`", key),
+                    ))
+                }
+            }
+        }
+
+        let Some(map_line) = map_line else {
+            return Err(LevelParseError::new(
+                text.lines().count().max(1),
+                1,
+                "missing `map:` section",
+            ));
+        };
+        let tileset = tileset
+            .ok_or_else(|| LevelParseError::new(map_line, 1, "no `tileset` set before `map:`"))?;
+        let background = background.ok_or_else(|| {
+            LevelParseError::new(map_line, 1, "no `background` set before `map:`")
+        })?;
+
+        let mut rows: Vec<(usize, &str)> = lines.collect();
+        // Blank lines at the end of the file would otherwise become rows under the floor
+        while rows.last().is_some_and(|(_, row)| row.trim().is_empty()) {
+            rows.pop();
+        }
+        let height = rows.len();
+        let width = rows
+            .iter()
+            .map(|(_, row)| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        if width == 0 {
+            return Err(LevelParseError::new(map_line, 1, "the map is empty"));
+        }
+
+        let mut tiles = vec![None; width * height];
+        let mut spawn = None;
+        let mut goal = None;
+        for (row, (line_no, line)) in rows.iter().enumerate() {
+            // Rows are written top first, but stored bottom first
+            let y = height - 1 - row;
+            for (x, c) in line.chars().enumerate() {
+                let at = || UVec2::new(x as u32, y as u32);
+                match c {
+                    ' ' | '.' => {}
+                    'P' if spawn.is_none() => spawn = Some(at()),
+                    'G' if goal.is_none() => goal = Some(at()),
+                    'P' | 'G' => {
+                        return Err(LevelParseError::new(
+                            *line_no,
+                            x + 1,
+                            format!("more than one `{}` in the map", c),
+                        ))
+                    }
+                    _ => match legend.get(&c) {
+                        Some(index) => tiles[y * width + x] = Some(*index),
+                        None => {
+                            return Err(LevelParseError::new(
+                                *line_no,
+                                x + 1,
+                                format!("`{}` has no `tile` line giving its atlas index", c),
+                            ))
+                        }
+                    },
+                }
+            }
+        }
+
+        Ok(Self {
+            width,
+            height,
+            layers: vec![TileLayer {
+                tileset,
+                tiles,
+                properties: Properties::from([(
+                    SOLID_PROPERTY.to_string(),
+                    PropertyValue::Bool(true),
+                )]),
+            }],
+            objects: Vec::new(),
+            spawn: spawn
+                .ok_or_else(|| LevelParseError::new(map_line, 1, "the map has no `P` spawn"))?
+                .as_vec2(),
+            goal: goal
+                .ok_or_else(|| LevelParseError::new(map_line, 1, "the map has no `G` goal"))?
+                .as_vec2(),
+            background,
+            music,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct LevelParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl LevelParseError {
+    fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for LevelParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for LevelParseError {}
+
+#[derive(Default)]
+pub struct LevelDataLoader;
+
+#[derive(Error, Debug)]
+pub enum LevelDataLoaderError {
+    #[error("Could not read level: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Level is not valid utf-8: {0}")]
+    Utf8(#[from] std::string::FromUtf8Error),
+    #[error("Could not parse level, {0}")]
+    Parse(#[from] LevelParseError),
+}
+
+impl AssetLoader for LevelDataLoader {
+    type Asset = LevelData;
+    type Settings = ();
+    type Error = LevelDataLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(LevelData::parse(&String::from_utf8(bytes)?)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "tileset = bricks\nbackground = sky\ntile # = 0\ntile é = 1\nmap:\n";
+
+    fn parse_err(text: &str) -> (usize, usize, String) {
+        let e = LevelData::parse(text).unwrap_err();
+        (e.line, e.column, e.message)
+    }
+
+    #[test]
+    fn parses_a_level() {
+        let level = LevelData::parse(&format!("{} P  G\n#é##", HEADER)).unwrap();
+        assert_eq!((level.width, level.height), (5, 2));
+        assert_eq!(level.spawn, Vec2::new(1., 1.));
+        assert_eq!(level.goal, Vec2::new(4., 1.));
+        assert_eq!(level.tile(0, 1, 0), Some(1));
+        assert!(level.is_solid(0, 0) && !level.is_solid(0, 1));
+        assert_eq!(level.background, "sky");
+    }
+
+    #[test]
+    fn bad_legend() {
+        assert_eq!(
+            parse_err("  tile é = nope\n"),
+            (1, 12, "expected an atlas index, found `nope`".to_string())
+        );
+        assert_eq!(
+            parse_err("tileset = bricks\n  tile P = 0\n"),
+            (
+                2,
+                3,
+                "`P` is reserved and can't be used as a tile".to_string()
+            )
+        );
+        assert_eq!(
+            parse_err("é\n"),
+            (
+                1,
+                1,
+                "expected `key = value`, a `tile` line or `map:`".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_tile() {
+        assert_eq!(
+            parse_err(&format!("{}P  G\néé?#", HEADER)),
+            (
+                7,
+                3,
+                "`?` has no `tile` line giving its atlas index".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn missing_spawn_and_goal() {
+        assert_eq!(
+            parse_err(&format!("{}   G\n####", HEADER)),
+            (5, 1, "the map has no `P` spawn".to_string())
+        );
+        assert_eq!(
+            parse_err(&format!("{}P\n####", HEADER)),
+            (5, 1, "the map has no `G` goal".to_string())
+        );
+    }
+}
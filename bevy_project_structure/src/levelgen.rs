@@ -0,0 +1,268 @@
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::iter::repeat;
+
+use crate::{
+    level_data::{LevelData, Properties, PropertyValue, TileLayer, SOLID_PROPERTY},
+    loading::LoadingSet,
+    movement::MovementConfig,
+    GameState, GENERATED_LEN, TILE_SIZE, WIN_H,
+};
+
+// Key the generated level is stored under in place of the campaign
+pub const GENERATED_LEVEL: &str = "generated";
+
+// Flat ground at each end of the level, in tiles
+const START_RUN: usize = 8;
+const END_RUN: usize = 6;
+const PLATFORM_WIDTH: (usize, usize) = (3, 8);
+const MAX_GAP: usize = 4;
+const GAP_CHANCE: f64 = 0.4;
+const LEDGE_CHANCE: f64 = 0.3;
+const DECORATION_CHANCE: f64 = 0.15;
+// Only count on this much of the player's best jump, to leave room for error
+const JUMP_MARGIN: f32 = 0.8;
+
+pub const TILESET: &str = "bricks";
+pub const BACKGROUND: &str = "background";
+const DECORATION_TILE: usize = 3;
+
+#[derive(Resource, Deref)]
+pub struct LevelSeed(u64);
+
+// A level built from the seed given on the command line
+#[derive(Resource, Deref)]
+pub struct GeneratedLevel(Handle<LevelData>);
+
+pub struct LevelGenPlugin {
+    pub seed: Option<u64>,
+}
+
+impl Plugin for LevelGenPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(seed) = self.seed else {
+            return;
+        };
+        // Built again whenever the movement tuning changes, such as once its
+        // config file loads, but not while the level is being played
+        app.insert_resource(LevelSeed(seed)).add_systems(
+            Update,
+            generate_level
+                .run_if(resource_changed::<MovementConfig>)
+                .run_if(not(in_state(GameState::Playing)))
+                .before(LoadingSet::Manifest),
+        );
+    }
+}
+
+fn generate_level(
+    mut commands: Commands,
+    seed: Res<LevelSeed>,
+    movement_config: Res<MovementConfig>,
+    generated_level: Option<Res<GeneratedLevel>>,
+    mut levels: ResMut<Assets<LevelData>>,
+) {
+    info!("Generating level from seed {}", **seed);
+    let level = generate(**seed, GENERATED_LEN, &movement_config);
+    match generated_level {
+        Some(generated_level) => levels.insert(&**generated_level, level),
+        None => commands.insert_resource(GeneratedLevel(levels.add(level))),
+    }
+}
+
+// The same seed always gives the same level. Every jump along the way is
+// checked against the player's movement, so the goal can always be reached.
+pub fn generate(seed: u64, length: usize, movement_config: &MovementConfig) -> LevelData {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let height = (WIN_H / TILE_SIZE) as usize;
+    // Keep some headroom over the highest ground for jumping
+    let max_ground = height - 3;
+
+    // Solid tiles in each column, from the bottom up. 0 is a gap.
+    let mut ground = vec![1; START_RUN];
+    let mut ground_height = 1;
+    let mut run_up = START_RUN;
+    let mut ledges = Vec::new();
+
+    while ground.len() < length.saturating_sub(END_RUN) {
+        let mut next_height =
+            (ground_height as i32 + rng.gen_range(-2..=2)).clamp(1, max_ground as i32) as usize;
+        let mut gap = if rng.gen_bool(GAP_CHANCE) {
+            rng.gen_range(1..=MAX_GAP)
+        } else {
+            0
+        };
+
+        let rise = |next_height: usize| next_height as i32 - ground_height as i32;
+        while gap > 0 && !can_jump(gap, rise(next_height), run_up, movement_config) {
+            gap -= 1;
+        }
+        if !can_jump(gap, rise(next_height), run_up, movement_config) {
+            next_height = ground_height;
+        }
+
+        let width = rng.gen_range(PLATFORM_WIDTH.0..=PLATFORM_WIDTH.1);
+        // A ledge over the platform, high enough to walk under
+        if width >= 4 && next_height + 3 < height && rng.gen_bool(LEDGE_CHANCE) {
+            let start = ground.len() + gap + 1;
+            ledges.push((start..start + width - 2, next_height + 2));
+        }
+
+        ground.extend(repeat(0).take(gap));
+        ground.extend(repeat(next_height).take(width));
+        ground_height = next_height;
+        run_up = width;
+    }
+    ground.extend(repeat(ground_height).take(END_RUN));
+
+    let width = ground.len();
+    let mut tiles = vec![None; width * height];
+    let mut decorations = vec![None; width * height];
+    for (x, column) in ground.iter().enumerate() {
+        for y in 0..*column {
+            tiles[y * width + x] = Some((x + y) % 4);
+        }
+        if *column > 0 && x > START_RUN && rng.gen_bool(DECORATION_CHANCE) {
+            decorations[*column * width + x] = Some(DECORATION_TILE);
+        }
+    }
+    for (columns, y) in ledges {
+        for x in columns {
+            tiles[y * width + x] = Some(x % 4);
+        }
+    }
+
+    let solid = Properties::from([(SOLID_PROPERTY.to_string(), PropertyValue::Bool(true))]);
+    LevelData {
+        width,
+        height,
+        layers: vec![
+            TileLayer {
+                tileset: TILESET.to_string(),
+                tiles,
+                properties: solid,
+            },
+            TileLayer {
+                tileset: TILESET.to_string(),
+                tiles: decorations,
+                properties: Properties::default(),
+            },
+        ],
+        objects: Vec::new(),
+        spawn: Vec2::new(2., ground[2] as f32),
+        goal: Vec2::new((width - 3) as f32, ground[width - 3] as f32),
+        background: BACKGROUND.to_string(),
+        music: None,
+    }
+}
+
+// Whether the player can clear `gap` columns and land `rise` tiles higher (or
+// lower), taking off at whatever speed a run-up of `run_up` tiles allows
+fn can_jump(gap: usize, rise: i32, run_up: usize, movement_config: &MovementConfig) -> bool {
+    let (gravity, jump_speed) = (movement_config.gravity, movement_config.jump_speed);
+    let rise = rise as f32 * TILE_SIZE;
+    let peak = jump_speed * jump_speed / (2. * gravity);
+    if rise > peak * JUMP_MARGIN {
+        return false;
+    }
+    if gap == 0 {
+        return true;
+    }
+
+    let speed = (2. * movement_config.acceleration * run_up as f32 * TILE_SIZE)
+        .sqrt()
+        .min(movement_config.max_speed);
+    // Time until coming back down to the landing height
+    let airtime = (jump_speed + (jump_speed * jump_speed - 2. * gravity * rise).sqrt()) / gravity;
+    speed * airtime * JUMP_MARGIN >= (gap + 1) as f32 * TILE_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FIXED_HZ;
+
+    fn tiles(level: &LevelData) -> Vec<&[Option<usize>]> {
+        level
+            .layers
+            .iter()
+            .map(|layer| layer.tiles.as_slice())
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_level() {
+        let movement_config = MovementConfig::default();
+        let a = generate(7, GENERATED_LEN, &movement_config);
+        let b = generate(7, GENERATED_LEN, &movement_config);
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        assert_eq!(tiles(&a), tiles(&b));
+        assert_eq!((a.spawn, a.goal), (b.spawn, b.goal));
+
+        let other = generate(8, GENERATED_LEN, &movement_config);
+        assert_ne!(tiles(&a), tiles(&other));
+    }
+
+    // Runs from a standstill across `run_up` tiles and jumps off the edge on
+    // fixed ticks, the way the player does, returning how far it gets before
+    // coming back down to `rise` tiles above where it took off
+    fn jump_distance(rise: i32, run_up: usize, movement_config: &MovementConfig) -> f32 {
+        let deltat = 1. / FIXED_HZ as f32;
+        let edge = run_up as f32 * TILE_SIZE;
+        let rise = rise as f32 * TILE_SIZE;
+        let (mut position, mut velocity) = (Vec2::ZERO, Vec2::ZERO);
+        loop {
+            velocity.x =
+                (velocity.x + movement_config.acceleration * deltat).min(movement_config.max_speed);
+            if position.x >= edge && position.y == 0. {
+                velocity.y = movement_config.jump_speed;
+            }
+            if position.y > 0. || velocity.y > 0. {
+                velocity.y = (velocity.y - movement_config.gravity * deltat)
+                    .max(-movement_config.max_fall_speed);
+            }
+            position += velocity * deltat;
+            if velocity.y < 0. && position.y < rise {
+                return position.x - edge;
+            }
+        }
+    }
+
+    #[test]
+    fn accepted_jumps_can_be_cleared() {
+        // The defaults, and a slower run with a weaker jump
+        let weak = MovementConfig {
+            max_speed: 400.,
+            jump_speed: 1000.,
+            ..default()
+        };
+        for movement_config in [MovementConfig::default(), weak] {
+            assert_jumps_clear(&movement_config);
+        }
+    }
+
+    fn assert_jumps_clear(movement_config: &MovementConfig) {
+        let mut accepted = 0;
+        for gap in 1..=MAX_GAP {
+            for rise in -3..=3 {
+                for run_up in PLATFORM_WIDTH.0..=PLATFORM_WIDTH.1 {
+                    if !can_jump(gap, rise, run_up, movement_config) {
+                        continue;
+                    }
+                    accepted += 1;
+                    let distance = jump_distance(rise, run_up, movement_config);
+                    assert!(
+                        distance >= (gap + 1) as f32 * TILE_SIZE,
+                        "gap {} rise {} run-up {}: only cleared {}",
+                        gap,
+                        rise,
+                        run_up,
+                        distance
+                    );
+                }
+            }
+        }
+        assert!(accepted > 0);
+    }
+}
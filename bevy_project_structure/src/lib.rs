@@ -0,0 +1,246 @@
+use bevy::{prelude::*, window::PresentMode};
+use serde::Deserialize;
+
+mod actions;
+mod animation;
+mod camera;
+mod campaign;
+pub mod chunks;
+#[cfg(feature = "dev")]
+mod hot_reload;
+mod interpolation;
+mod ldtk;
+pub mod level;
+pub mod level_data;
+pub mod levelgen;
+pub mod loading;
+mod manifest;
+mod menu;
+mod movement;
+mod music;
+mod pack;
+mod pack_format;
+mod placeholder;
+mod player;
+mod replay;
+mod settings;
+mod sfx;
+mod tiled;
+mod time_trial;
+mod win;
+
+const TITLE: &str = "Better Bevy Project Setup";
+const WIN_W: f32 = 1280.;
+const WIN_H: f32 = 720.;
+
+// Gameplay ticks per second, whatever the display refresh rate
+const FIXED_HZ: f64 = 60.;
+
+// How far (in world units) the player can move around the middle of the view
+// before the camera follows, and roughly how long it takes to catch up
+const CAMERA_DEADZONE: Vec2 = Vec2::new(200., 150.);
+const CAMERA_SMOOTH_TIME: f32 = 0.15;
+// The camera looks ahead of the player by how far they'd go in this many seconds
+const CAMERA_LOOK_AHEAD: f32 = 0.3;
+const CAMERA_MAX_LOOK_AHEAD: Vec2 = Vec2::new(200., 100.);
+// Furthest the camera is thrown by a full-trauma shake, and how quickly it wobbles
+const MAX_SHAKE_OFFSET: Vec2 = Vec2::new(40., 30.);
+const MAX_SHAKE_ANGLE: f32 = 0.05;
+const SHAKE_FREQUENCY: f32 = 15.;
+// Trauma and punch zoom lost per second, and how long a flash takes to fade
+const TRAUMA_DECAY: f32 = 1.2;
+const PUNCH_DECAY: f32 = 0.4;
+const FLASH_TIME: f32 = 0.3;
+// Shake noise seed for campaign levels, as generated ones use their own seed
+const SHAKE_SEED: u64 = 0x5eed;
+// Camera effects when the player falls out of the level or lands hard
+const FALL_TRAUMA: f32 = 0.6;
+const FALL_FLASH: Color = Color::srgba(1., 0.2, 0.2, 0.4);
+const HARD_LANDING_SPEED: f32 = 1400.;
+const LANDING_PUNCH: f32 = 0.04;
+const LANDING_TRAUMA: f32 = 0.3;
+
+const PLAYER_SIZE: f32 = 100.;
+// Defaults for the movement config, which the config file can override
+pub const PLAYER_SPEED: f32 = 500.;
+const ACCELERATION: f32 = 5000.;
+const DECELERATION: f32 = 5000.;
+const TURN_RATE: f32 = 8000.;
+const JUMP_SPEED: f32 = 1200.;
+const GRAVITY: f32 = 3000.;
+const MAX_FALL_SPEED: f32 = 1500.;
+const JUMP_CUT: f32 = 0.5;
+const COYOTE_TIME: f32 = 0.1;
+const JUMP_BUFFER_TIME: f32 = 0.1;
+// Above this speed the player runs rather than walks
+const RUN_SPEED: f32 = 400.;
+const MOVEMENT_CONFIG_PATH: &str = "player.movement.ron";
+
+const TILE_SIZE: f32 = 100.;
+// Level columns per chunk, and how many chunks past the screen edge stay spawned
+const CHUNK_WIDTH: usize = 16;
+const CHUNK_MARGIN: usize = 1;
+
+const PROGRESS_LENGTH: f32 = 120.;
+const PROGRESS_HEIGHT: f32 = 20.;
+const PROGRESS_FRAME: f32 = 5.;
+// Default for how long the first loading screen stays up, in seconds
+const MIN_LOAD_TIME: f32 = 5.;
+
+const PACK_PATH: &str = "assets.pack";
+
+const LEVEL_COMPLETE_TIME: f32 = 2.;
+const SAVE_PATH: &str = "save.ron";
+const BINDINGS_PATH: &str = "bindings.ron";
+const SETTINGS_PATH: &str = "settings.ron";
+// How much one press changes a volume or the screen shake in the settings menu
+const VOLUME_STEP: f32 = 0.1;
+const SHAKE_STEP: f32 = 0.25;
+
+// Seconds for one track to fade into the next, and for music to duck and come back
+const MUSIC_FADE_TIME: f32 = 1.5;
+const MUSIC_DUCK_TIME: f32 = 0.3;
+// How loud ducked music is, compared to normal
+const MUSIC_DUCK_VOLUME: f32 = 0.3;
+// Manifest key of the sound effects, and how many can play at once
+const SFX_BANK: &str = "player_sounds";
+const SFX_VOICE_LIMIT: usize = 8;
+// How far a stick has to be pushed to count as pressing a bound direction
+const AXIS_THRESHOLD: f32 = 0.5;
+// Default for how far a stick can drift from the centre without moving the player
+const STICK_DEADZONE: f32 = 0.2;
+
+// Fastest run through each level, for time trials
+const GHOSTS_PATH: &str = "ghosts.ron";
+// How see-through the ghost of the best run is
+const GHOST_ALPHA: f32 = 0.4;
+// Times taken along each level, the last one at the goal
+const SPLITS: usize = 4;
+
+// How much faster a recording plays when fast-forwarded
+const REPLAY_FAST_FORWARD: f32 = 4.;
+
+// Length of a generated level, in tiles
+const GENERATED_LEN: usize = 120;
+
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    Loading,
+    Playing,
+    LevelComplete,
+    Win,
+    LoadError,
+}
+
+// Everything lives in the library so benches can build parts of the game.
+// The game binary just calls this.
+pub fn run() {
+    // Play a level generated from a seed instead of the campaign: cargo run -- --seed 1234
+    let mut seed = arg_value("--seed").map(|seed| seed.parse().expect("--seed should be a number"));
+    // Save the input to a file while playing (--record run.ron), or play one back (--replay run.ron)
+    let record = arg_value("--record");
+    let replay = arg_value("--replay").map(|path| {
+        replay::load_replay(&path)
+            .unwrap_or_else(|e| panic!("Could not load recording {}: {}", path, e))
+    });
+    // Race against a ghost of the best run through each level
+    let time_trial = std::env::args().any(|arg| arg == "--time-trial");
+    // Keep the loading screen up for at least this many seconds: --min-load-time 0
+    let min_load_time = arg_value("--min-load-time").map_or(MIN_LOAD_TIME, |time| {
+        time.parse()
+            .ok()
+            .filter(|time: &f32| *time >= 0.)
+            .expect("--min-load-time should be a number of seconds")
+    });
+    // A recording of a generated level brings its seed along
+    if let Some(replay) = &replay {
+        seed = replay.seed;
+    }
+
+    let mut app = App::new();
+    app
+        // Read from the packed archive instead of loose files, if there is one
+        .add_plugins(pack::PackPlugin { path: PACK_PATH })
+        // Setup Bevy and game window
+        .add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: String::from(TITLE),
+                resolution: (WIN_W, WIN_H).into(),
+                present_mode: PresentMode::Fifo,
+                ..default()
+            }),
+            ..default()
+        }));
+    add_game(&mut app, seed, min_load_time);
+    app.add_plugins((
+        replay::ReplayPlugin { record, replay },
+        time_trial::TimeTrialPlugin {
+            enabled: time_trial,
+        },
+    ));
+
+    // Pick up edits to assets while the game is running
+    #[cfg(feature = "dev")]
+    app.add_plugins(hot_reload::HotReloadPlugin);
+
+    // Run the game
+    app.run();
+}
+
+// Everything apart from Bevy's own plugins, so tests can run the game without a window
+fn add_game(app: &mut App, seed: Option<u64>, min_load_time: f32) {
+    app.insert_resource(ClearColor(Color::Srgba(Srgba::gray(0.25))))
+        // Set initial state
+        .init_state::<GameState>()
+        // Add general systems
+        .add_systems(Startup, setup_camera)
+        .add_systems(OnEnter(GameState::MainMenu), log_state_change)
+        .add_systems(OnEnter(GameState::Loading), log_state_change)
+        .add_systems(OnEnter(GameState::Playing), log_state_change)
+        .add_systems(OnEnter(GameState::LevelComplete), log_state_change)
+        .add_systems(OnEnter(GameState::Win), log_state_change)
+        .add_systems(OnEnter(GameState::LoadError), log_state_change)
+        // Add all subsystems
+        .add_plugins((
+            loading::LoadingPlugin { min_load_time },
+            actions::ActionPlugin,
+            interpolation::InterpolationPlugin,
+            settings::SettingsPlugin,
+            camera::CameraPlugin {
+                shake_seed: seed.unwrap_or(SHAKE_SEED),
+            },
+            music::MusicPlugin,
+            sfx::SfxPlugin,
+            animation::AnimationPlugin,
+            movement::MovementPlugin,
+            player::PlayerPlugin,
+            level::LevelPlugin,
+            chunks::ChunkPlugin,
+            campaign::CampaignPlugin,
+            levelgen::LevelGenPlugin { seed },
+        ))
+        .add_plugins((win::WinPlugin, menu::MenuPlugin));
+}
+
+// The argument after `name`, if it was given
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != name);
+    args.next()?;
+    Some(
+        args.next()
+            .unwrap_or_else(|| panic!("{} needs a value", name)),
+    )
+}
+
+fn setup_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle::default(),
+        camera::CameraController::default(),
+    ));
+}
+
+fn log_state_change(state: Res<State<GameState>>) {
+    info!("Just moved to {:?}!", state.get());
+}
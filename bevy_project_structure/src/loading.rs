@@ -1,6 +1,21 @@
-use bevy::{asset::LoadState, prelude::*};
+use bevy::{
+    asset::{LoadState, RecursiveDependencyLoadState, UntypedAssetId},
+    prelude::*,
+    tasks::{block_on, futures_lite::AsyncSeekExt, poll_once, IoTaskPool, Task},
+    time::Stopwatch,
+    utils::HashMap,
+};
+use std::{io::SeekFrom, time::Duration};
 
-use crate::{GameState, PROGRESS_FRAME, PROGRESS_HEIGHT, PROGRESS_LENGTH};
+use crate::{
+    animation::AnimationClips,
+    level_data::LevelData,
+    levelgen::{self, GeneratedLevel, GENERATED_LEVEL},
+    manifest::{AssetKind, AssetManifest, AssetManifestLoader, AtlasGrid, ManifestEntry},
+    placeholder::{checker_image, silent_audio},
+    sfx::SoundBank,
+    GameState, PROGRESS_FRAME, PROGRESS_HEIGHT, PROGRESS_LENGTH, SFX_BANK,
+};
 
 #[derive(Component)]
 struct LoadingProgressFrame;
@@ -8,33 +23,381 @@ struct LoadingProgressFrame;
 #[derive(Component)]
 struct LoadingProgress;
 
+#[derive(Component)]
+struct LoadingDetails;
+
+#[derive(Component)]
+struct LoadErrorScreen;
+
+pub struct LoadingAsset {
+    pub handle: UntypedHandle,
+    pub state: LoadState,
+    pub dependency_state: RecursiveDependencyLoadState,
+    // File size, used to weight progress once the probe finishes
+    pub bytes: Option<u64>,
+    size_probe: Option<Task<Option<u64>>>,
+}
+
+impl LoadingAsset {
+    pub fn new(handle: UntypedHandle, asset_server: &AssetServer) -> Self {
+        let size_probe = probe_size(asset_server, &handle);
+        Self {
+            handle,
+            state: LoadState::NotLoaded,
+            dependency_state: RecursiveDependencyLoadState::NotLoaded,
+            bytes: None,
+            size_probe,
+        }
+    }
+
+    // Failed optional assets already have a placeholder, so failure is also done
+    fn done(&self) -> bool {
+        matches!(self.state, LoadState::Failed(_))
+            || self.dependency_state == RecursiveDependencyLoadState::Loaded
+    }
+
+    fn path(&self) -> String {
+        self.handle
+            .path()
+            .map_or(String::from("???"), |p| format!("{:?}", p))
+    }
+}
+
 #[derive(Resource, Deref, DerefMut)]
-pub struct LoadingAssets(pub Vec<(UntypedHandle, LoadState)>);
+pub struct LoadingAssets(pub Vec<LoadingAsset>);
 
 #[derive(Resource, Deref, DerefMut)]
-pub struct TimedLoad(Timer);
+struct MinLoadTime(Duration);
+
+// Anything choosing which group to stream should run between these
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LoadingSet {
+    Manifest,
+    Stream,
+}
+
+#[derive(Resource, Default, Deref, DerefMut)]
+struct LoadingClock(Stopwatch);
+
+#[derive(Resource)]
+struct ManifestHandle(Handle<AssetManifest>);
+
+// Required assets that failed to load, listed on the LoadError screen
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct LoadErrors(pub Vec<String>);
+
+// Typed handles for everything listed in the asset manifest, looked up by key.
+// Keys in REQUIRED_ASSETS are checked before this exists, so they're always there.
+#[derive(Resource, Default)]
+pub struct GameAssets {
+    images: HashMap<String, Handle<Image>>,
+    audio: HashMap<String, Handle<AudioSource>>,
+    levels: HashMap<String, Handle<LevelData>>,
+    animations: HashMap<String, Handle<AnimationClips>>,
+    sound_banks: HashMap<String, Handle<SoundBank>>,
+    layouts: HashMap<String, Handle<TextureAtlasLayout>>,
+    entries: HashMap<UntypedAssetId, (String, ManifestEntry)>,
+    campaign: Vec<String>,
+    music: HashMap<GameState, String>,
+}
+
+impl GameAssets {
+    pub fn image(&self, key: &str) -> Option<Handle<Image>> {
+        self.images.get(key).cloned()
+    }
+
+    pub fn audio(&self, key: &str) -> Option<Handle<AudioSource>> {
+        self.audio.get(key).cloned()
+    }
+
+    pub fn level(&self, key: &str) -> Option<Handle<LevelData>> {
+        self.levels.get(key).cloned()
+    }
+
+    pub fn animation(&self, key: &str) -> Option<Handle<AnimationClips>> {
+        self.animations.get(key).cloned()
+    }
+
+    pub fn sound_bank(&self, key: &str) -> Option<Handle<SoundBank>> {
+        self.sound_banks.get(key).cloned()
+    }
+
+    // Level keys in the order they're played
+    pub fn campaign(&self) -> &[String] {
+        &self.campaign
+    }
+
+    // Audio key of the music for `state`, if it has any
+    pub fn music(&self, state: GameState) -> Option<&str> {
+        self.music.get(&state).map(String::as_str)
+    }
+
+    pub fn layout(&self, key: &str) -> Option<Handle<TextureAtlasLayout>> {
+        self.layouts.get(key).cloned()
+    }
+
+    fn load_entry(
+        &mut self,
+        key: &str,
+        entry: &ManifestEntry,
+        asset_server: &AssetServer,
+        texture_atlases: &mut Assets<TextureAtlasLayout>,
+    ) -> UntypedHandle {
+        info!("Queued {:?} for {:?}: {}", key, entry.state, entry.path);
+        let handle = match entry.kind {
+            AssetKind::Image => {
+                let handle: Handle<Image> = asset_server.load(&entry.path);
+                self.images.insert(key.to_string(), handle.clone());
+                handle.untyped()
+            }
+            AssetKind::Audio => {
+                let handle: Handle<AudioSource> = asset_server.load(&entry.path);
+                self.audio.insert(key.to_string(), handle.clone());
+                handle.untyped()
+            }
+            AssetKind::Level => {
+                let handle: Handle<LevelData> = asset_server.load(&entry.path);
+                self.levels.insert(key.to_string(), handle.clone());
+                handle.untyped()
+            }
+            AssetKind::Animation => {
+                let handle: Handle<AnimationClips> = asset_server.load(&entry.path);
+                self.animations.insert(key.to_string(), handle.clone());
+                handle.untyped()
+            }
+            AssetKind::SoundBank => {
+                let handle: Handle<SoundBank> = asset_server.load(&entry.path);
+                self.sound_banks.insert(key.to_string(), handle.clone());
+                handle.untyped()
+            }
+        };
+        self.entries
+            .insert(handle.id(), (key.to_string(), entry.clone()));
+
+        if let Some(grid) = entry.atlas {
+            self.layouts
+                .insert(key.to_string(), texture_atlases.add(grid_layout(grid)));
+        }
+
+        handle
+    }
 
-const MIN_LOAD_TIME: f32 = 5.;
+    // Drop every handle belonging to a group, unless the same key was already
+    // reloaded for the group replacing it
+    fn drop_group(&mut self, group: &str) {
+        let dropped: Vec<UntypedAssetId> = self
+            .entries
+            .iter()
+            .filter(|(_, (_, entry))| entry.group.as_deref() == Some(group))
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in dropped {
+            let Some((key, _)) = self.entries.remove(&id) else {
+                continue;
+            };
+            if self
+                .images
+                .get(&key)
+                .is_some_and(|h| h.id().untyped() == id)
+            {
+                self.images.remove(&key);
+                self.layouts.remove(&key);
+            }
+            if self.audio.get(&key).is_some_and(|h| h.id().untyped() == id) {
+                self.audio.remove(&key);
+            }
+            if self
+                .levels
+                .get(&key)
+                .is_some_and(|h| h.id().untyped() == id)
+            {
+                self.levels.remove(&key);
+            }
+            if self
+                .animations
+                .get(&key)
+                .is_some_and(|h| h.id().untyped() == id)
+            {
+                self.animations.remove(&key);
+            }
+            if self
+                .sound_banks
+                .get(&key)
+                .is_some_and(|h| h.id().untyped() == id)
+            {
+                self.sound_banks.remove(&key);
+            }
+            info!("Dropped {:?} from group {:?}", key, group);
+        }
+    }
+}
+
+// Used by hot reloading to follow edits to the manifest and images
+#[cfg(feature = "dev")]
+impl GameAssets {
+    pub fn entry(&self, id: UntypedAssetId) -> Option<(&str, &ManifestEntry)> {
+        self.entries
+            .get(&id)
+            .map(|(key, entry)| (key.as_str(), entry))
+    }
+
+    // Swap in a rebuilt layout under the existing handle, so sprites using it pick it up
+    pub fn rebuild_layout(
+        &self,
+        key: &str,
+        grid: AtlasGrid,
+        texture_atlases: &mut Assets<TextureAtlasLayout>,
+    ) {
+        if let Some(handle) = self.layouts.get(key) {
+            texture_atlases.insert(handle, grid_layout(grid));
+        }
+    }
+
+    // Bring already loaded keys in line with an edited manifest, returns true if anything changed
+    pub fn apply_manifest(
+        &mut self,
+        manifest: &AssetManifest,
+        asset_server: &AssetServer,
+        texture_atlases: &mut Assets<TextureAtlasLayout>,
+    ) -> bool {
+        let loaded: Vec<(UntypedAssetId, String, ManifestEntry)> = self
+            .entries
+            .iter()
+            .map(|(id, (key, entry))| (*id, key.clone(), entry.clone()))
+            .collect();
+
+        let mut changed = false;
+        for (id, key, old) in loaded {
+            let Some(new) = manifest.assets.get(&key) else {
+                continue;
+            };
+            if new.path != old.path || new.kind != old.kind {
+                info!("Reloading {:?} from {}", key, new.path);
+                self.entries.remove(&id);
+                self.load_entry(&key, new, asset_server, texture_atlases);
+                changed = true;
+            } else if new.atlas != old.atlas {
+                info!("Rebuilding atlas layout for {:?}", key);
+                match new.atlas {
+                    Some(grid) if self.layouts.contains_key(&key) => {
+                        self.rebuild_layout(&key, grid, texture_atlases)
+                    }
+                    Some(grid) => {
+                        self.layouts
+                            .insert(key.clone(), texture_atlases.add(grid_layout(grid)));
+                    }
+                    None => {
+                        self.layouts.remove(&key);
+                    }
+                }
+                self.entries.insert(id, (key, new.clone()));
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn grid_layout(grid: AtlasGrid) -> TextureAtlasLayout {
+    TextureAtlasLayout::from_grid(
+        UVec2::splat(grid.tile_size),
+        grid.columns,
+        grid.rows,
+        None,
+        None,
+    )
+}
+
+// Sent when assets are swapped out while playing, so anything holding their handles can resync
+#[derive(Event, Default)]
+pub struct AssetsReloaded;
+
+// Level-scoped assets: the active group's handles are live, and the next group
+// streams in the background until the game passes through Loading again
+#[derive(Resource, Default)]
+pub struct AssetGroups {
+    active: Option<String>,
+    next: Option<String>,
+    queued: bool,
+}
+
+impl AssetGroups {
+    pub fn stream(&mut self, group: &str) {
+        if self.next.as_deref() != Some(group) {
+            self.next = Some(group.to_string());
+            self.queued = false;
+        }
+    }
+}
+
+const MANIFEST_PATH: &str = "assets.manifest.ron";
+
+// Keys the code looks up by name, and whether they need an atlas grid. They're
+// used outside any one level, so they can't be in a group either.
+const REQUIRED_ASSETS: [(&str, AssetKind, bool); 4] = [
+    ("player", AssetKind::Image, true),
+    ("player_clips", AssetKind::Animation, false),
+    (SFX_BANK, AssetKind::SoundBank, false),
+    ("win", AssetKind::Image, false),
+];
+// A generated level doesn't come with a file naming its tileset and background
+const GENERATED_LEVEL_ASSETS: [(&str, AssetKind, bool); 2] = [
+    (levelgen::TILESET, AssetKind::Image, true),
+    (levelgen::BACKGROUND, AssetKind::Image, false),
+];
+
+pub struct LoadingPlugin {
+    // Keep the loading screen up at least this long, in seconds (0 to skip)
+    pub min_load_time: f32,
+}
 
-pub struct LoadingPlugin;
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(LoadingAssets(Vec::new()))
+            .insert_resource(MinLoadTime(Duration::from_secs_f32(self.min_load_time)))
+            .init_resource::<LoadErrors>()
+            .init_resource::<AssetGroups>()
+            .add_event::<AssetsReloaded>()
+            .init_asset::<AssetManifest>()
+            .init_asset_loader::<AssetManifestLoader>()
+            .add_systems(Startup, load_manifest)
             .add_systems(OnEnter(GameState::Loading), setup_loading)
-            .add_systems(Update, update_loading.run_if(in_state(GameState::Loading)))
-            .add_systems(Update, load_timer)
+            .add_systems(
+                Update,
+                (
+                    load_manifest_assets
+                        .run_if(not(resource_exists::<GameAssets>))
+                        .run_if(in_state(GameState::Loading))
+                        .in_set(LoadingSet::Manifest),
+                    stream_asset_group
+                        .run_if(resource_exists::<GameAssets>)
+                        .in_set(LoadingSet::Stream),
+                    (update_loading, show_loading_progress).run_if(in_state(GameState::Loading)),
+                )
+                    .chain(),
+            )
             .add_systems(
                 OnExit(GameState::Loading),
                 (
                     despawn_with::<LoadingProgressFrame>,
                     despawn_with::<LoadingProgress>,
+                    despawn_with::<LoadingDetails>,
+                    activate_asset_group,
                     free_loading_handles,
+                    clear_min_load_time,
                 ),
-            );
+            )
+            .add_systems(OnEnter(GameState::LoadError), setup_load_error);
     }
 }
 
-fn setup_loading(mut commands: Commands) {
+fn setup_loading(mut commands: Commands, mut camera: Query<&mut Transform, With<Camera>>) {
+    // The first time round this runs before Startup has spawned the camera, which starts at 0
+    if let Ok(mut ct) = camera.get_single_mut() {
+        ct.translation.x = 0.;
+    }
+
     commands.spawn((
         SpriteBundle {
             transform: Transform {
@@ -69,69 +432,465 @@ fn setup_loading(mut commands: Commands) {
         LoadingProgress,
     ));
 
-    commands.insert_resource(TimedLoad(Timer::from_seconds(
-        MIN_LOAD_TIME,
-        TimerMode::Once,
-    )));
-    info!("Loading: Fake timed asset");
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_sections([
+                TextSection::new("0%", TextStyle::default()),
+                TextSection::new("", TextStyle::default()),
+                TextSection::new("", TextStyle::default()),
+            ]),
+            transform: Transform::from_xyz(0., -(PROGRESS_HEIGHT + PROGRESS_FRAME) * 1.5, 0.),
+            ..default()
+        },
+        LoadingDetails,
+    ));
+
+    commands.insert_resource(LoadingClock::default());
+}
+
+fn load_manifest(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut loading_assets: ResMut<LoadingAssets>,
+) {
+    let manifest_handle = asset_server.load(MANIFEST_PATH);
+    loading_assets.push(LoadingAsset::new(
+        manifest_handle.clone().untyped(),
+        &asset_server,
+    ));
+    commands.insert_resource(ManifestHandle(manifest_handle));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn load_manifest_assets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    manifests: Res<Assets<AssetManifest>>,
+    manifest_handle: Res<ManifestHandle>,
+    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut loading_assets: ResMut<LoadingAssets>,
+    mut load_errors: ResMut<LoadErrors>,
+    generated_level: Option<Res<GeneratedLevel>>,
+) {
+    let Some(manifest) = manifests.get(&manifest_handle.0) else {
+        return;
+    };
+
+    let errors = check_manifest(manifest, generated_level.is_some());
+    if !errors.is_empty() {
+        // Nothing gets to look keys up, update_loading moves on to LoadError
+        load_errors.extend(errors);
+        return;
+    }
+
+    let mut game_assets = GameAssets {
+        campaign: manifest.levels.clone(),
+        music: manifest.music.clone(),
+        ..default()
+    };
+    // A generated level is played on its own
+    if let Some(generated_level) = generated_level {
+        let key = GENERATED_LEVEL.to_string();
+        game_assets
+            .levels
+            .insert(key.clone(), (**generated_level).clone());
+        game_assets.campaign = vec![key];
+    }
+    for (key, entry) in manifest.assets.iter() {
+        if entry.group.is_some() {
+            continue;
+        }
+        let handle = game_assets.load_entry(key, entry, &asset_server, &mut texture_atlases);
+        loading_assets.push(LoadingAsset::new(handle, &asset_server));
+    }
+
+    commands.insert_resource(game_assets);
+}
+
+// Everything wrong with the manifest, for the LoadError screen
+fn check_manifest(manifest: &AssetManifest, generated_level: bool) -> Vec<String> {
+    let mut errors = Vec::new();
+    if manifest.levels.is_empty() {
+        errors.push(format!("{}: no levels listed", MANIFEST_PATH));
+    }
+    for key in manifest.levels.iter() {
+        match manifest.assets.get(key) {
+            Some(entry) if entry.kind == AssetKind::Level => {}
+            _ => errors.push(format!("{}: {:?} is not a level", MANIFEST_PATH, key)),
+        }
+    }
+    for key in manifest.music.values() {
+        match manifest.assets.get(key) {
+            Some(entry) if entry.kind == AssetKind::Audio => {}
+            _ => errors.push(format!("{}: {:?} is not audio", MANIFEST_PATH, key)),
+        }
+    }
+
+    let generated: &[_] = if generated_level {
+        &GENERATED_LEVEL_ASSETS
+    } else {
+        &[]
+    };
+    for (key, kind, atlas) in REQUIRED_ASSETS.iter().chain(generated) {
+        let Some(entry) = manifest.assets.get(*key) else {
+            errors.push(format!("{}: no {:?} asset", MANIFEST_PATH, key));
+            continue;
+        };
+        if entry.kind != *kind {
+            errors.push(format!(
+                "{}: {:?} should be {:?}, not {:?}",
+                MANIFEST_PATH, key, kind, entry.kind
+            ));
+        }
+        if *atlas && entry.atlas.is_none() {
+            errors.push(format!("{}: {:?} needs an atlas grid", MANIFEST_PATH, key));
+        }
+        if entry.group.is_some() {
+            errors.push(format!(
+                "{}: {:?} is used by every level, so it can't be in a group",
+                MANIFEST_PATH, key
+            ));
+        }
+    }
+    errors
+}
+
+fn check_level(path: &str, level: &LevelData, game_assets: &GameAssets) -> Vec<String> {
+    let mut errors = Vec::new();
+    for layer in level.layers.iter() {
+        let error = format!(
+            "{}: tileset {:?} is not an image with an atlas grid in {}",
+            path, layer.tileset, MANIFEST_PATH
+        );
+        let found = game_assets.image(&layer.tileset).is_some()
+            && game_assets.layout(&layer.tileset).is_some();
+        if !found && !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
+    if game_assets.image(&level.background).is_none() {
+        errors.push(format!(
+            "{}: background {:?} is not an image in {}",
+            path, level.background, MANIFEST_PATH
+        ));
+    }
+    errors
+}
+
+fn stream_asset_group(
+    asset_server: Res<AssetServer>,
+    manifests: Res<Assets<AssetManifest>>,
+    manifest_handle: Res<ManifestHandle>,
+    mut asset_groups: ResMut<AssetGroups>,
+    mut game_assets: ResMut<GameAssets>,
+    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut loading_assets: ResMut<LoadingAssets>,
+) {
+    if asset_groups.queued {
+        return;
+    }
+    let Some(next) = asset_groups.next.clone() else {
+        return;
+    };
+    let Some(manifest) = manifests.get(&manifest_handle.0) else {
+        return;
+    };
+
+    // Still holding the handles from last time, nothing to stream
+    if asset_groups.active.as_ref() != Some(&next) {
+        info!("Streaming asset group {:?}", next);
+        for (key, entry) in manifest.assets.iter() {
+            if entry.group.as_ref() != Some(&next) {
+                continue;
+            }
+            let handle = game_assets.load_entry(key, entry, &asset_server, &mut texture_atlases);
+            loading_assets.push(LoadingAsset::new(handle, &asset_server));
+        }
+    }
+    asset_groups.queued = true;
+}
+
+fn activate_asset_group(
+    mut asset_groups: ResMut<AssetGroups>,
+    game_assets: Option<ResMut<GameAssets>>,
+) {
+    if !asset_groups.queued || asset_groups.active == asset_groups.next {
+        return;
+    }
+
+    if let (Some(active), Some(mut game_assets)) = (asset_groups.active.take(), game_assets) {
+        game_assets.drop_group(&active);
+    }
+    asset_groups.active = asset_groups.next.clone();
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_loading(
+    time: Res<Time>,
     asset_server: Res<AssetServer>,
     mut loading_assets: ResMut<LoadingAssets>,
-    mut loading_progress: Query<&mut Transform, With<LoadingProgress>>,
+    mut loading_clock: ResMut<LoadingClock>,
+    min_load_time: Res<MinLoadTime>,
     mut next_state: ResMut<NextState<GameState>>,
-    timed_load: Res<TimedLoad>,
+    game_assets: Option<Res<GameAssets>>,
+    mut images: ResMut<Assets<Image>>,
+    mut audio: ResMut<Assets<AudioSource>>,
+    levels: Res<Assets<LevelData>>,
+    mut load_errors: ResMut<LoadErrors>,
 ) {
-    let mut progress_transform = loading_progress.single_mut();
+    loading_clock.tick(time.delta());
 
-    for (handle, prev_state) in loading_assets.iter_mut() {
-        let new_loadstate = asset_server.load_state(&*handle);
-        let path = handle
-            .path()
-            .map_or(String::from("???"), |p| format!("{:?}", p));
-        if new_loadstate != *prev_state {
-            match new_loadstate {
-                LoadState::Failed(_) => warn!("{:?}: {}", new_loadstate, path),
-                _ => info!("{:?}: {}", new_loadstate, path),
+    for asset in loading_assets.iter_mut() {
+        if let Some(probe) = asset.size_probe.as_mut() {
+            if let Some(bytes) = block_on(poll_once(probe)) {
+                asset.bytes = bytes;
+                asset.size_probe = None;
             }
-            *prev_state = new_loadstate;
         }
-    }
 
-    let loaded: usize = loading_assets
-        .iter()
-        .map(|i| match i {
-            (_, LoadState::Loaded) => 1,
-            _ => 0,
-        })
-        .sum::<usize>()
-        + if timed_load.finished() { 1 } else { 0 };
-    // account for fake TimedLoad "Asset"
-    let total = loading_assets.len() + 1;
-    let percent = (loaded as f32) / (total as f32);
+        let new_loadstate = asset_server.load_state(&asset.handle);
+        if new_loadstate != asset.state {
+            match &new_loadstate {
+                LoadState::Failed(err) => {
+                    warn!("{:?}: {}", new_loadstate, asset.path());
 
-    progress_transform.scale.x = PROGRESS_LENGTH * percent;
+                    let entry = game_assets
+                        .as_ref()
+                        .and_then(|game_assets| game_assets.entries.get(&asset.handle.id()));
+                    let substituted = match entry {
+                        Some((key, entry)) if entry.optional => {
+                            warn!("Using placeholder for optional asset {:?}", key);
+                            substitute_placeholder(
+                                asset.handle.id(),
+                                entry,
+                                &mut images,
+                                &mut audio,
+                            )
+                        }
+                        _ => false,
+                    };
+                    if !substituted {
+                        load_errors.push(format!("{}: {}", asset.path(), err));
+                    }
+                }
+                _ => info!("{:?}: {}", new_loadstate, asset.path()),
+            }
+            asset.state = new_loadstate;
+        }
+
+        // An asset only counts once everything it depends on has loaded too
+        let new_dependency_state = asset_server.recursive_dependency_load_state(&asset.handle);
+        if new_dependency_state != asset.dependency_state {
+            if new_dependency_state == RecursiveDependencyLoadState::Failed
+                && asset.state == LoadState::Loaded
+            {
+                warn!("Dependency failed: {}", asset.path());
+                load_errors.push(format!("{}: a dependency failed to load", asset.path()));
+            }
+            // Levels name their tilesets and background, which the manifest has to have
+            let level = asset
+                .handle
+                .id()
+                .try_typed::<LevelData>()
+                .ok()
+                .and_then(|id| levels.get(id));
+            if let (Some(level), Some(game_assets)) = (level, game_assets.as_ref()) {
+                if new_dependency_state == RecursiveDependencyLoadState::Loaded {
+                    load_errors.extend(check_level(&asset.path(), level, game_assets));
+                }
+            }
+            asset.dependency_state = new_dependency_state;
+        }
+    }
+
+    if !load_errors.is_empty() {
+        next_state.set(GameState::LoadError);
+        return;
+    }
 
     // Check if all assets are loaded
-    if loaded == total {
+    if loading_assets.iter().all(LoadingAsset::done) && loading_clock.elapsed() >= **min_load_time {
         next_state.set(GameState::Playing);
     }
 }
 
-fn load_timer(time: Res<Time>, mut timed_load: ResMut<TimedLoad>) {
-    timed_load.tick(time.delta());
-    if timed_load.just_finished() {
-        info!("Loaded: Fake timed asset");
+fn show_loading_progress(
+    loading_assets: Res<LoadingAssets>,
+    loading_clock: Res<LoadingClock>,
+    mut loading_progress: Query<&mut Transform, With<LoadingProgress>>,
+    mut loading_details: Query<&mut Text, With<LoadingDetails>>,
+) {
+    let mut progress_transform = loading_progress.single_mut();
+    let mut details = loading_details.single_mut();
+
+    // Weight by file size, assuming average size for files not yet probed
+    let probed: Vec<u64> = loading_assets.iter().filter_map(|a| a.bytes).collect();
+    let average = if probed.is_empty() {
+        1
+    } else {
+        (probed.iter().sum::<u64>() / probed.len() as u64).max(1)
+    };
+    let weight = |asset: &LoadingAsset| asset.bytes.unwrap_or(average).max(1);
+
+    let total: u64 = loading_assets.iter().map(weight).sum();
+    let loaded: u64 = loading_assets.iter().filter(|a| a.done()).map(weight).sum();
+    let percent = if total == 0 {
+        1.
+    } else {
+        (loaded as f32) / (total as f32)
+    };
+
+    progress_transform.scale.x = PROGRESS_LENGTH * percent;
+
+    let current = loading_assets
+        .iter()
+        .find(|a| !a.done())
+        .map_or(String::new(), LoadingAsset::path);
+    details.sections[0].value = format!("{:.0}%", percent * 100.);
+    details.sections[1].value = format!("  {}  ", current);
+    details.sections[2].value = format!("{:.1}s", loading_clock.elapsed_secs());
+}
+
+fn probe_size(asset_server: &AssetServer, handle: &UntypedHandle) -> Option<Task<Option<u64>>> {
+    let path = handle.path()?.clone_owned();
+    let asset_server = asset_server.clone();
+
+    Some(IoTaskPool::get().spawn(async move {
+        let source = asset_server.get_source(path.source().clone()).ok()?;
+        let mut reader = source.reader().read(path.path()).await.ok()?;
+        reader.seek(SeekFrom::End(0)).await.ok()
+    }))
+}
+
+// Returns false for kinds that have no sensible placeholder
+fn substitute_placeholder(
+    id: UntypedAssetId,
+    entry: &ManifestEntry,
+    images: &mut Assets<Image>,
+    audio: &mut Assets<AudioSource>,
+) -> bool {
+    match entry.kind {
+        AssetKind::Image => {
+            let size = entry
+                .atlas
+                .map(|grid| UVec2::new(grid.columns, grid.rows) * grid.tile_size);
+            images.insert(id.typed::<Image>(), checker_image(size));
+            true
+        }
+        AssetKind::Audio => {
+            audio.insert(id.typed::<AudioSource>(), silent_audio());
+            true
+        }
+        AssetKind::Level | AssetKind::Animation | AssetKind::SoundBank => false,
     }
 }
 
+fn setup_load_error(mut commands: Commands, load_errors: Res<LoadErrors>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(10.),
+                    ..default()
+                },
+                ..default()
+            },
+            LoadErrorScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Failed to load required assets",
+                TextStyle {
+                    font_size: 40.,
+                    ..default()
+                },
+            ));
+            for error in load_errors.iter() {
+                parent.spawn(TextBundle::from_section(
+                    error,
+                    TextStyle {
+                        font_size: 20.,
+                        ..default()
+                    },
+                ));
+            }
+        });
+}
+
 fn free_loading_handles(mut loading_assets: ResMut<LoadingAssets>) {
     loading_assets.clear();
 }
 
+// Only the first trip through Loading is held open, level changes move on as soon as they can
+fn clear_min_load_time(mut min_load_time: ResMut<MinLoadTime>) {
+    **min_load_time = Duration::ZERO;
+}
+
 pub fn despawn_with<T: Component>(mut commands: Commands, q: Query<Entity, With<T>>) {
     for e in q.iter() {
         commands.entity(e).despawn_recursive();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(assets: &str) -> AssetManifest {
+        ron::from_str(&format!(
+            "(levels: [\"level1\"], music: {{Playing: \"tune\"}}, assets: {{{}}})",
+            assets
+        ))
+        .unwrap()
+    }
+
+    const LEVEL_AND_MUSIC: &str = r#"
+        "level1": (path: "level1.level", kind: Level, state: Playing, group: Some("level1")),
+        "tune": (path: "tune.ogg", kind: Audio, state: Playing),
+    "#;
+
+    #[test]
+    fn shipped_manifest_has_every_required_asset() {
+        let manifest: AssetManifest =
+            ron::from_str(include_str!("../assets/assets.manifest.ron")).unwrap();
+        assert!(check_manifest(&manifest, false).is_empty());
+        assert!(check_manifest(&manifest, true).is_empty());
+    }
+
+    #[test]
+    fn missing_and_mistyped_assets_are_reported() {
+        let manifest = manifest(&format!(
+            "{}{}",
+            LEVEL_AND_MUSIC,
+            r#"
+            "player": (path: "walking.png", kind: Image, state: Playing),
+            "player_clips": (path: "walking.clips.ron", kind: Animation, state: Playing,
+                group: Some("level1")),
+            "win": (path: "win.ogg", kind: Audio, state: Win),
+            "#
+        ));
+        let path = MANIFEST_PATH;
+        assert_eq!(
+            check_manifest(&manifest, false),
+            [
+                format!("{}: \"player\" needs an atlas grid", path),
+                format!(
+                    "{}: \"player_clips\" is used by every level, so it can't be in a group",
+                    path
+                ),
+                format!("{}: no \"player_sounds\" asset", path),
+                format!("{}: \"win\" should be Image, not Audio", path),
+            ]
+        );
+
+        // A generated level needs its tileset and background too
+        let errors = check_manifest(&manifest, true);
+        assert!(errors.contains(&format!("{}: no \"bricks\" asset", path)));
+        assert!(errors.contains(&format!("{}: no \"background\" asset", path)));
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::GameState;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetKind {
+    Image,
+    Audio,
+    Level,
+    Animation,
+    SoundBank,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasGrid {
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_size: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub kind: AssetKind,
+    #[serde(default)]
+    pub atlas: Option<AtlasGrid>,
+    pub state: GameState,
+    // Optional assets get a placeholder instead of failing the load
+    #[serde(default)]
+    pub optional: bool,
+    // Grouped assets are only loaded while their group (usually a level) is active
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+// Every asset the game uses, keyed by the name gameplay code looks it up with
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct AssetManifest {
+    // Level keys in the order they're played. Each level is streamed in with the
+    // group of the same name.
+    pub levels: Vec<String>,
+    pub assets: HashMap<String, ManifestEntry>,
+    // Audio key of the music for each state, where there is some. A level can
+    // ask for its own music instead.
+    #[serde(default)]
+    pub music: HashMap<GameState, String>,
+}
+
+#[derive(Default)]
+pub struct AssetManifestLoader;
+
+#[derive(Error, Debug)]
+pub enum AssetManifestLoaderError {
+    #[error("Could not read asset manifest: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse asset manifest: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for AssetManifestLoader {
+    type Asset = AssetManifest;
+    type Settings = ();
+    type Error = AssetManifestLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["manifest.ron"]
+    }
+}
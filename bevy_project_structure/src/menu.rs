@@ -0,0 +1,466 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    actions::{Action, ControlsMenu, ControlsMenuSet},
+    campaign::{CurrentLevel, Progress},
+    loading::despawn_with,
+    settings::{save_settings, Settings},
+    GameState, SHAKE_STEP, VOLUME_STEP,
+};
+
+// Menus over the main menu or a level in progress. While a level is paused
+// virtual time stops, so no fixed ticks run and gameplay stays frozen, but the
+// world is still drawn under the menu.
+#[derive(SubStates, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[source(GameState = GameState::MainMenu | GameState::Playing)]
+pub(crate) enum Menu {
+    // Either the main menu itself, or playing
+    #[default]
+    None,
+    Paused,
+    Settings,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MainItem {
+    Start,
+    Continue,
+    Settings,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PauseItem {
+    Resume,
+    Settings,
+    MainMenu,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SettingsItem {
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    ScreenShake,
+    Flashes,
+    Fullscreen,
+    Controls,
+    Back,
+}
+
+const MAIN_ITEMS: [MainItem; 4] = [
+    MainItem::Start,
+    MainItem::Continue,
+    MainItem::Settings,
+    MainItem::Quit,
+];
+
+const PAUSE_ITEMS: [PauseItem; 4] = [
+    PauseItem::Resume,
+    PauseItem::Settings,
+    PauseItem::MainMenu,
+    PauseItem::Quit,
+];
+
+const SETTINGS_ITEMS: [SettingsItem; 8] = [
+    SettingsItem::MasterVolume,
+    SettingsItem::MusicVolume,
+    SettingsItem::SfxVolume,
+    SettingsItem::ScreenShake,
+    SettingsItem::Flashes,
+    SettingsItem::Fullscreen,
+    SettingsItem::Controls,
+    SettingsItem::Back,
+];
+
+// Which screen is showing, if any
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Screen {
+    Main,
+    Pause,
+    Settings,
+}
+
+impl Screen {
+    fn current(game_state: GameState, menu: Menu) -> Option<Self> {
+        match (game_state, menu) {
+            (_, Menu::Settings) => Some(Screen::Settings),
+            (_, Menu::Paused) => Some(Screen::Pause),
+            (GameState::MainMenu, Menu::None) => Some(Screen::Main),
+            _ => None,
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            Screen::Main => MAIN_ITEMS.len(),
+            Screen::Pause => PAUSE_ITEMS.len(),
+            Screen::Settings => SETTINGS_ITEMS.len(),
+        }
+    }
+}
+
+// Index of the highlighted item on the screen showing
+#[derive(Resource, Default, Deref, DerefMut)]
+struct MenuCursor(usize);
+
+#[derive(Component)]
+struct MenuScreen;
+
+// The menu actions move between items, Confirm chooses, the move actions
+// change values, and Pause goes back
+pub struct MenuPlugin;
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_sub_state::<Menu>()
+            .init_resource::<MenuCursor>()
+            .add_systems(OnEnter(Menu::None), (resume_time, reset_cursor))
+            .add_systems(OnEnter(Menu::Paused), (pause_time, reset_cursor))
+            .add_systems(OnEnter(Menu::Settings), reset_cursor)
+            .add_systems(OnExit(Menu::Settings), save_changed_settings)
+            .add_systems(
+                Update,
+                (navigate_menu, show_menu)
+                    .chain()
+                    .after(ControlsMenuSet)
+                    .run_if(in_state(GameState::MainMenu).or_else(in_state(GameState::Playing))),
+            )
+            .add_systems(OnExit(GameState::MainMenu), despawn_with::<MenuScreen>)
+            .add_systems(OnExit(GameState::Playing), despawn_with::<MenuScreen>);
+    }
+}
+
+fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn resume_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+fn reset_cursor(mut cursor: ResMut<MenuCursor>) {
+    **cursor = 0;
+}
+
+fn save_changed_settings(settings: Res<Settings>) {
+    save_settings(&settings);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn navigate_menu(
+    actions: Res<ButtonInput<Action>>,
+    game_state: Res<State<GameState>>,
+    menu: Res<State<Menu>>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut next_menu: ResMut<NextState<Menu>>,
+    mut cursor: ResMut<MenuCursor>,
+    mut settings: ResMut<Settings>,
+    mut controls_menu: ResMut<ControlsMenu>,
+    mut current_level: ResMut<CurrentLevel>,
+    progress: Res<Progress>,
+    mut exit: EventWriter<AppExit>,
+) {
+    // The controls menu has the input to itself while it's open
+    if controls_menu.is_open() {
+        return;
+    }
+    let Some(screen) = Screen::current(*game_state.get(), *menu.get()) else {
+        if actions.just_pressed(Action::Pause) {
+            next_menu.set(Menu::Paused);
+        }
+        return;
+    };
+
+    if actions.just_pressed(Action::MenuUp) {
+        **cursor = cursor.saturating_sub(1);
+        return;
+    }
+    if actions.just_pressed(Action::MenuDown) {
+        **cursor = (**cursor + 1).min(screen.len() - 1);
+        return;
+    }
+    let confirm = actions.just_pressed(Action::Confirm);
+    let back = actions.just_pressed(Action::Pause);
+    // -1, 0 or 1
+    let change = actions.just_pressed(Action::MoveRight) as i32
+        - actions.just_pressed(Action::MoveLeft) as i32;
+
+    match screen {
+        Screen::Main if confirm => match MAIN_ITEMS[**cursor] {
+            MainItem::Start => {
+                **current_level = 0;
+                next_game_state.set(GameState::Loading);
+            }
+            MainItem::Continue if progress.unlocked > 0 => {
+                **current_level = progress.unlocked;
+                next_game_state.set(GameState::Loading);
+            }
+            MainItem::Continue => {}
+            MainItem::Settings => next_menu.set(Menu::Settings),
+            MainItem::Quit => {
+                exit.send(AppExit::Success);
+            }
+        },
+        Screen::Main => {}
+        Screen::Pause if back => next_menu.set(Menu::None),
+        Screen::Pause if confirm => match PAUSE_ITEMS[**cursor] {
+            PauseItem::Resume => next_menu.set(Menu::None),
+            PauseItem::Settings => next_menu.set(Menu::Settings),
+            PauseItem::MainMenu => {
+                // Otherwise the pause menu would carry over, as the main menu
+                // has the same sub-state
+                next_menu.set(Menu::None);
+                next_game_state.set(GameState::MainMenu);
+            }
+            PauseItem::Quit => {
+                exit.send(AppExit::Success);
+            }
+        },
+        Screen::Pause => {}
+        Screen::Settings => {
+            let item = SETTINGS_ITEMS[**cursor];
+            if back || (confirm && item == SettingsItem::Back) {
+                // Back to wherever settings were opened from
+                next_menu.set(if *game_state.get() == GameState::Playing {
+                    Menu::Paused
+                } else {
+                    Menu::None
+                });
+            } else if confirm && item == SettingsItem::Controls {
+                controls_menu.open();
+            } else if confirm || change != 0 {
+                change_setting(&mut settings, item, change);
+            }
+        }
+    }
+}
+
+// Moves a value up or down a step, or flips a switch whichever way it's asked
+fn change_setting(settings: &mut Settings, item: SettingsItem, change: i32) {
+    let step = |value: f32, step: f32| (value + change as f32 * step).clamp(0., 1.);
+    match item {
+        SettingsItem::MasterVolume => {
+            settings.master_volume = step(settings.master_volume, VOLUME_STEP)
+        }
+        SettingsItem::MusicVolume => {
+            settings.music_volume = step(settings.music_volume, VOLUME_STEP)
+        }
+        SettingsItem::SfxVolume => settings.sfx_volume = step(settings.sfx_volume, VOLUME_STEP),
+        SettingsItem::ScreenShake => {
+            settings.screen_shake = step(settings.screen_shake, SHAKE_STEP)
+        }
+        SettingsItem::Flashes => settings.flashes = !settings.flashes,
+        SettingsItem::Fullscreen => settings.fullscreen = !settings.fullscreen,
+        SettingsItem::Controls | SettingsItem::Back => {}
+    }
+}
+
+fn item_labels(screen: Screen, settings: &Settings, progress: &Progress) -> Vec<(String, bool)> {
+    let percent = |value: f32| format!("< {:.0}% >", value * 100.);
+    let on_off = |value: bool| if value { "On" } else { "Off" }.to_string();
+    match screen {
+        Screen::Main => MAIN_ITEMS
+            .iter()
+            .map(|item| match item {
+                MainItem::Start => ("Start".to_string(), true),
+                MainItem::Continue => (
+                    format!("Continue from level {}", progress.unlocked + 1),
+                    progress.unlocked > 0,
+                ),
+                MainItem::Settings => ("Settings".to_string(), true),
+                MainItem::Quit => ("Quit".to_string(), true),
+            })
+            .collect(),
+        Screen::Pause => PAUSE_ITEMS
+            .iter()
+            .map(|item| match item {
+                PauseItem::Resume => "Resume",
+                PauseItem::Settings => "Settings",
+                PauseItem::MainMenu => "Main menu",
+                PauseItem::Quit => "Quit",
+            })
+            .map(|label| (label.to_string(), true))
+            .collect(),
+        Screen::Settings => SETTINGS_ITEMS
+            .iter()
+            .map(|item| match item {
+                SettingsItem::MasterVolume => {
+                    format!("Master volume: {}", percent(settings.master_volume))
+                }
+                SettingsItem::MusicVolume => {
+                    format!("Music volume: {}", percent(settings.music_volume))
+                }
+                SettingsItem::SfxVolume => {
+                    format!("Effects volume: {}", percent(settings.sfx_volume))
+                }
+                SettingsItem::ScreenShake => {
+                    format!("Screen shake: {}", percent(settings.screen_shake))
+                }
+                SettingsItem::Flashes => format!("Screen flashes: {}", on_off(settings.flashes)),
+                SettingsItem::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+                SettingsItem::Controls => "Controls...".to_string(),
+                SettingsItem::Back => "Back".to_string(),
+            })
+            .map(|label| (label, true))
+            .collect(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show_menu(
+    mut commands: Commands,
+    game_state: Res<State<GameState>>,
+    menu: Res<State<Menu>>,
+    cursor: Res<MenuCursor>,
+    settings: Res<Settings>,
+    progress: Res<Progress>,
+    screens: Query<Entity, With<MenuScreen>>,
+) {
+    if !game_state.is_changed()
+        && !menu.is_changed()
+        && !cursor.is_changed()
+        && !settings.is_changed()
+    {
+        return;
+    }
+    for entity in screens.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(screen) = Screen::current(*game_state.get(), *menu.get()) else {
+        return;
+    };
+
+    let (title, hint) = match screen {
+        Screen::Main => ("Main menu", "Up/Down to choose, Confirm to select"),
+        Screen::Pause => (
+            "Paused",
+            "Up/Down to choose, Confirm to select, Pause to resume",
+        ),
+        Screen::Settings => (
+            "Settings",
+            "Up/Down to choose, Left/Right or Confirm to change, Pause to go back",
+        ),
+    };
+    // Over the level when paused, but leaving it visible
+    let background = if screen == Screen::Main {
+        Color::BLACK
+    } else {
+        Color::srgba(0., 0., 0., 0.6)
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(10.),
+                    ..default()
+                },
+                background_color: background.into(),
+                z_index: ZIndex::Global(50),
+                ..default()
+            },
+            MenuScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font_size: 40.,
+                    ..default()
+                },
+            ));
+            for (i, (label, enabled)) in item_labels(screen, &settings, &progress)
+                .into_iter()
+                .enumerate()
+            {
+                let selected = i == **cursor;
+                let color = if !enabled {
+                    Color::srgb(0.5, 0.5, 0.5)
+                } else if selected {
+                    Color::srgb(1., 0.85, 0.2)
+                } else {
+                    Color::WHITE
+                };
+                parent.spawn(TextBundle::from_section(
+                    format!("{}{}", if selected { "> " } else { "  " }, label),
+                    TextStyle {
+                        font_size: 24.,
+                        color,
+                        ..default()
+                    },
+                ));
+            }
+            parent.spawn(TextBundle::from_section(
+                hint,
+                TextStyle {
+                    font_size: 16.,
+                    ..default()
+                },
+            ));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::state::app::StatesPlugin;
+
+    fn menu_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin))
+            .insert_state(GameState::Playing)
+            .init_resource::<ButtonInput<Action>>()
+            .init_resource::<ControlsMenu>()
+            .init_resource::<Progress>()
+            .insert_resource(CurrentLevel(0))
+            .insert_resource(Settings::default())
+            .add_plugins(MenuPlugin);
+        app.update();
+        app
+    }
+
+    // Presses for a single frame, as the input plugin isn't there to release them
+    fn press_action(app: &mut App, action: Action) {
+        app.world_mut()
+            .resource_mut::<ButtonInput<Action>>()
+            .press(action);
+        app.update();
+        app.world_mut()
+            .resource_mut::<ButtonInput<Action>>()
+            .reset_all();
+        app.update();
+    }
+
+    #[test]
+    fn pause_menu_returns_to_main_menu() {
+        let mut app = menu_app();
+        press_action(&mut app, Action::Pause);
+        assert_eq!(*app.world().resource::<State<Menu>>().get(), Menu::Paused);
+        assert!(app.world().resource::<Time<Virtual>>().is_paused());
+
+        // Down to "Main menu"
+        press_action(&mut app, Action::MenuDown);
+        press_action(&mut app, Action::MenuDown);
+        press_action(&mut app, Action::Confirm);
+
+        let world = app.world_mut();
+        assert_eq!(
+            *world.resource::<State<GameState>>().get(),
+            GameState::MainMenu
+        );
+        assert_eq!(*world.resource::<State<Menu>>().get(), Menu::None);
+        assert!(!world.resource::<Time<Virtual>>().is_paused());
+        let title = world
+            .query_filtered::<&Text, Without<MenuScreen>>()
+            .iter(world)
+            .next()
+            .map(|text| text.sections[0].value.clone());
+        assert_eq!(title.as_deref(), Some("Main menu"));
+    }
+}
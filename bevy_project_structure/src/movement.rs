@@ -0,0 +1,139 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    ACCELERATION, COYOTE_TIME, DECELERATION, GRAVITY, JUMP_BUFFER_TIME, JUMP_CUT, JUMP_SPEED,
+    MAX_FALL_SPEED, MOVEMENT_CONFIG_PATH, PLAYER_SPEED, TURN_RATE,
+};
+
+// How the player moves. Speeds are in pixels per second, rates in pixels per
+// second squared and times in seconds. Anything left out of the config file
+// keeps its default from lib.rs.
+#[derive(Asset, Resource, Reflect, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct MovementConfig {
+    pub max_speed: f32,
+    // Speeding up from a standstill or in the direction already moving
+    pub acceleration: f32,
+    // Slowing down with no direction held
+    pub deceleration: f32,
+    // Slowing down while holding the opposite direction
+    pub turn_rate: f32,
+    pub gravity: f32,
+    pub jump_speed: f32,
+    pub max_fall_speed: f32,
+    // Fraction of upward speed kept when jump is let go early
+    pub jump_cut: f32,
+    // Grace period for jumping after running off a ledge
+    pub coyote_time: f32,
+    // Grace period for a jump pressed just before landing
+    pub jump_buffer_time: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        Self {
+            max_speed: PLAYER_SPEED,
+            acceleration: ACCELERATION,
+            deceleration: DECELERATION,
+            turn_rate: TURN_RATE,
+            gravity: GRAVITY,
+            jump_speed: JUMP_SPEED,
+            max_fall_speed: MAX_FALL_SPEED,
+            jump_cut: JUMP_CUT,
+            coyote_time: COYOTE_TIME,
+            jump_buffer_time: JUMP_BUFFER_TIME,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MovementConfigLoader;
+
+#[derive(Error, Debug)]
+pub enum MovementConfigLoaderError {
+    #[error("Could not read movement config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse movement config: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for MovementConfigLoader {
+    type Asset = MovementConfig;
+    type Settings = ();
+    type Error = MovementConfigLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["movement.ron"]
+    }
+}
+
+#[derive(Resource, Deref)]
+struct MovementConfigHandle(Handle<MovementConfig>);
+
+// Keeps the config file from replacing MovementConfig, such as while playing
+// back a recording made with other tuning
+#[derive(Resource)]
+pub struct LockedMovementConfig;
+
+// Starts out with the defaults, then takes the values from the config file once
+// it has loaded. With --features dev, saving the file applies it straight away.
+pub struct MovementPlugin;
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<MovementConfig>()
+            .init_asset::<MovementConfig>()
+            .init_asset_loader::<MovementConfigLoader>()
+            .init_resource::<MovementConfig>()
+            .add_systems(Startup, load_movement_config)
+            .add_systems(
+                Update,
+                apply_movement_config
+                    .run_if(on_event::<AssetEvent<MovementConfig>>())
+                    .run_if(not(resource_exists::<LockedMovementConfig>)),
+            );
+    }
+}
+
+fn load_movement_config(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(MovementConfigHandle(
+        asset_server.load(MOVEMENT_CONFIG_PATH),
+    ));
+}
+
+fn apply_movement_config(
+    mut config_events: EventReader<AssetEvent<MovementConfig>>,
+    configs: Res<Assets<MovementConfig>>,
+    handle: Res<MovementConfigHandle>,
+    mut movement_config: ResMut<MovementConfig>,
+) {
+    for event in config_events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event
+        else {
+            continue;
+        };
+        if *id != handle.id() {
+            continue;
+        }
+        if let Some(config) = configs.get(*id) {
+            info!("Movement config loaded from {}", MOVEMENT_CONFIG_PATH);
+            *movement_config = config.clone();
+        }
+    }
+}
@@ -1,44 +1,124 @@
-use bevy::{asset::LoadState, prelude::*};
+use bevy::{
+    audio::{PlaybackMode, Volume},
+    prelude::*,
+};
 
 use crate::{
-    loading::{despawn_with, LoadingAssets},
-    GameState,
+    level::ActiveLevel, level_data::LevelData, loading::GameAssets, settings::Settings, GameState,
+    MUSIC_DUCK_TIME, MUSIC_DUCK_VOLUME, MUSIC_FADE_TIME,
 };
 
-#[derive(Resource, Deref, DerefMut)]
-pub struct BackgroundMusic(Handle<AudioSource>);
+// A track that's playing, or fading in or out. While one track crossfades into
+// the next, both are playing.
+#[derive(Component)]
+struct MusicTrack {
+    key: String,
+    // How far faded in it is, from 0 to 1
+    fade: f32,
+    fading_out: bool,
+}
 
+// Turns the music down for as long as anything has this, such as a dialogue box
 #[derive(Component)]
-pub struct Music;
+pub struct DuckMusic;
 
-pub struct BackgroundMusicPlugin;
-impl Plugin for BackgroundMusicPlugin {
+// Plays the music for the current state, or the level's own music if it has
+// some, fading from one track to the next. The music is also turned down while
+// time is paused or anything has DuckMusic.
+pub struct MusicPlugin;
+impl Plugin for MusicPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, load_background_music)
-            .add_systems(OnEnter(GameState::Playing), start_background_music)
-            .add_systems(OnExit(GameState::Playing), despawn_with::<Music>);
+        app.add_systems(
+            Update,
+            (
+                choose_music.run_if(resource_exists::<GameAssets>),
+                fade_music,
+            )
+                .chain(),
+        );
     }
 }
 
-fn load_background_music(
+fn choose_music(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut loading_assets: ResMut<LoadingAssets>,
+    state: Res<State<GameState>>,
+    game_assets: Res<GameAssets>,
+    levels: Res<Assets<LevelData>>,
+    active_level: Option<Res<ActiveLevel>>,
+    mut tracks: Query<&mut MusicTrack>,
 ) {
-    let bg_music_handle = asset_server.load("bg_music.ogg");
-    loading_assets.push((bg_music_handle.clone().untyped(), LoadState::NotLoaded));
-    commands.insert_resource(BackgroundMusic(bg_music_handle));
-}
+    let level_music = active_level
+        .filter(|_| *state.get() == GameState::Playing)
+        .and_then(|active_level| levels.get(&**active_level))
+        .and_then(|level| level.music.as_deref())
+        // Warned about when the level loads
+        .filter(|key| game_assets.audio(key).is_some());
+    let wanted = level_music.or_else(|| game_assets.music(*state.get()));
 
-fn start_background_music(mut commands: Commands, background_music: Res<BackgroundMusic>) {
-    commands.spawn((
-        AudioBundle {
-            source: background_music.clone(),
-            settings: PlaybackSettings {
-                mode: bevy::audio::PlaybackMode::Loop,
-                ..default()
+    let mut found = false;
+    for mut track in tracks.iter_mut() {
+        let keep = Some(track.key.as_str()) == wanted;
+        // Only the newest track of a kind is kept, in case one was fading out
+        track.fading_out = !keep || found;
+        found |= keep;
+    }
+    // State music is checked when the manifest loads, but may be in a dropped group
+    let source = wanted.and_then(|key| game_assets.audio(key));
+    if let (Some(key), Some(source), false) = (wanted, source, found) {
+        commands.spawn((
+            AudioBundle {
+                source,
+                settings: PlaybackSettings {
+                    mode: PlaybackMode::Loop,
+                    volume: Volume::new(0.),
+                    ..default()
+                },
+            },
+            MusicTrack {
+                key: key.to_string(),
+                fade: 0.,
+                fading_out: false,
             },
-        },
-        Music,
-    ));
+        ));
+    }
+}
+
+// Also applies volume settings to what's already playing, so they take effect straight away
+fn fade_music(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    virtual_time: Res<Time<Virtual>>,
+    settings: Res<Settings>,
+    duckers: Query<(), With<DuckMusic>>,
+    mut tracks: Query<(Entity, &mut MusicTrack, Option<&AudioSink>)>,
+    // How far the music is turned down, from 0 to 1
+    mut ducked: Local<f32>,
+) {
+    let deltat = time.delta_seconds();
+    let duck = virtual_time.is_paused() || !duckers.is_empty();
+    let step = deltat / MUSIC_DUCK_TIME;
+    *ducked = if duck {
+        (*ducked + step).min(1.)
+    } else {
+        (*ducked - step).max(0.)
+    };
+    let volume =
+        settings.master_volume * settings.music_volume * (1. - *ducked * (1. - MUSIC_DUCK_VOLUME));
+
+    for (entity, mut track, sink) in tracks.iter_mut() {
+        let step = deltat / MUSIC_FADE_TIME;
+        if track.fading_out {
+            track.fade = (track.fade - step).max(0.);
+            if track.fade == 0. {
+                commands.entity(entity).despawn();
+                continue;
+            }
+        } else {
+            track.fade = (track.fade + step).min(1.);
+        }
+        // Not there until the track has loaded and started
+        if let Some(sink) = sink {
+            sink.set_volume(volume * track.fade);
+        }
+    }
 }
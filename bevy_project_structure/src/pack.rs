@@ -0,0 +1,126 @@
+use bevy::{
+    asset::io::{
+        AssetReader, AssetReaderError, AssetSource, AssetSourceId, PathStream, Reader, VecReader,
+    },
+    prelude::*,
+    utils::HashMap,
+};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::pack_format::{read_index, unpack_bytes, PackEntry};
+
+// Reads assets out of a single packed archive built by the pack_assets binary,
+// in place of the loose files under assets/
+pub struct PackPlugin {
+    pub path: &'static str,
+}
+
+impl Plugin for PackPlugin {
+    // Has to be added before DefaultPlugins, the default source can't change once AssetPlugin is built
+    fn build(&self, app: &mut App) {
+        if !Path::new(self.path).exists() {
+            return;
+        }
+
+        let index = match PackIndex::open(self.path) {
+            Ok(index) => Arc::new(index),
+            Err(e) => {
+                // Still playable if the loose assets are there. Reported once
+                // logging is set up, which is after this plugin.
+                let message = format!(
+                    "Could not read asset pack {}, using the assets folder instead: {}",
+                    self.path, e
+                );
+                app.add_systems(Startup, move || error!("{}", message));
+                return;
+            }
+        };
+
+        app.register_asset_source(
+            AssetSourceId::Default,
+            AssetSource::build().with_reader(move || {
+                Box::new(PackAssetReader {
+                    index: index.clone(),
+                })
+            }),
+        );
+    }
+}
+
+struct PackIndex {
+    path: PathBuf,
+    entries: HashMap<PathBuf, PackEntry>,
+}
+
+impl PackIndex {
+    fn open(path: &str) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        let pack_len = file.metadata()?.len();
+        let entries = read_index(&mut BufReader::new(file), pack_len)?
+            .into_iter()
+            .map(|entry| (PathBuf::from(&entry.path), entry))
+            .collect();
+
+        Ok(Self {
+            path: PathBuf::from(path),
+            entries,
+        })
+    }
+
+    fn read(&self, entry: &PackEntry) -> std::io::Result<Vec<u8>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+
+        // The index has checked this fits in the file
+        let mut stored = vec![0; entry.stored_len as usize];
+        file.read_exact(&mut stored)?;
+        unpack_bytes(entry, stored)
+    }
+}
+
+struct PackAssetReader {
+    index: Arc<PackIndex>,
+}
+
+impl AssetReader for PackAssetReader {
+    async fn read<'a>(&'a self, path: &'a Path) -> Result<Box<Reader<'a>>, AssetReaderError> {
+        let entry = self
+            .index
+            .entries
+            .get(path)
+            .ok_or_else(|| AssetReaderError::NotFound(path.to_path_buf()))?;
+
+        // A bad checksum fails this asset's load rather than the whole game
+        let bytes = self
+            .index
+            .read(entry)
+            .map_err(|e| AssetReaderError::Io(Arc::new(e)))?;
+        let reader: Box<Reader> = Box::new(VecReader::new(bytes));
+        Ok(reader)
+    }
+
+    // Packs don't carry .meta files
+    async fn read_meta<'a>(&'a self, path: &'a Path) -> Result<Box<Reader<'a>>, AssetReaderError> {
+        Err(AssetReaderError::NotFound(path.to_path_buf()))
+    }
+
+    async fn read_directory<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> Result<Box<PathStream>, AssetReaderError> {
+        Err(AssetReaderError::NotFound(path.to_path_buf()))
+    }
+
+    async fn is_directory<'a>(&'a self, path: &'a Path) -> Result<bool, AssetReaderError> {
+        Ok(self
+            .index
+            .entries
+            .keys()
+            .any(|p| p.starts_with(path) && p != path))
+    }
+}
@@ -0,0 +1,288 @@
+// On-disk layout of assets.pack, shared by the game and the pack_assets binary
+// (each only uses one side of it):
+//
+//   header:  b"CSPK", version: u32, entry count: u32
+//   index:   per entry: path len: u16, path (utf-8, '/' separated), offset: u64,
+//            stored len: u64, size: u64, crc32: u32, compressed: u8
+//   data:    each entry's bytes, deflated if compressed
+#![allow(dead_code)]
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &[u8; 4] = b"CSPK";
+pub const VERSION: u32 = 1;
+
+const HEADER_LEN: u64 = 12;
+const ENTRY_FIXED_LEN: u64 = 2 + 8 + 8 + 8 + 4 + 1;
+// Largest unpacked asset accepted, so a corrupt index can't ask for a huge allocation
+const MAX_ENTRY_SIZE: u64 = 1 << 30;
+
+#[derive(Debug, Clone)]
+pub struct PackEntry {
+    pub path: String,
+    pub offset: u64,
+    pub stored_len: u64,
+    pub size: u64,
+    pub crc32: u32,
+    pub compressed: bool,
+}
+
+// Deflate only when it actually saves space, returns the stored bytes and whether they're compressed
+pub fn pack_bytes(bytes: &[u8]) -> io::Result<(Vec<u8>, bool)> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    let deflated = encoder.finish()?;
+
+    if deflated.len() < bytes.len() {
+        Ok((deflated, true))
+    } else {
+        Ok((bytes.to_vec(), false))
+    }
+}
+
+// Inverse of pack_bytes, verifying the checksum of the original bytes
+pub fn unpack_bytes(entry: &PackEntry, stored: Vec<u8>) -> io::Result<Vec<u8>> {
+    let bytes = if entry.compressed {
+        let mut bytes = Vec::with_capacity(entry.size as usize);
+        // A byte more than expected is enough to tell it's wrong
+        DeflateDecoder::new(stored.as_slice())
+            .take(entry.size + 1)
+            .read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        stored
+    };
+
+    if bytes.len() as u64 != entry.size || crc32fast::hash(&bytes) != entry.crc32 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is corrupted (checksum mismatch)", entry.path),
+        ));
+    }
+    Ok(bytes)
+}
+
+// Where the data section starts for an index holding these entries
+pub fn data_offset(entries: &[PackEntry]) -> u64 {
+    HEADER_LEN
+        + entries
+            .iter()
+            .map(|e| ENTRY_FIXED_LEN + e.path.len() as u64)
+            .sum::<u64>()
+}
+
+pub fn write_index(writer: &mut impl Write, entries: &[PackEntry]) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(entries.len() as u32).to_le_bytes())?;
+
+    for entry in entries {
+        let path_len = u16::try_from(entry.path.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "asset path too long"))?;
+        writer.write_all(&path_len.to_le_bytes())?;
+        writer.write_all(entry.path.as_bytes())?;
+        writer.write_all(&entry.offset.to_le_bytes())?;
+        writer.write_all(&entry.stored_len.to_le_bytes())?;
+        writer.write_all(&entry.size.to_le_bytes())?;
+        writer.write_all(&entry.crc32.to_le_bytes())?;
+        writer.write_all(&[entry.compressed as u8])?;
+    }
+    Ok(())
+}
+
+// `pack_len` is the length of the whole file, which every entry has to fit in
+pub fn read_index(reader: &mut impl Read, pack_len: u64) -> io::Result<Vec<PackEntry>> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not an asset pack"));
+    }
+    let version = read_u32(reader)?;
+    if version != VERSION {
+        return Err(invalid_data(&format!(
+            "unsupported asset pack version {}",
+            version
+        )));
+    }
+
+    let count = read_u32(reader)?;
+    if count as u64 > pack_len.saturating_sub(HEADER_LEN) / ENTRY_FIXED_LEN {
+        return Err(invalid_data("asset pack index is longer than the file"));
+    }
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let mut path_len = [0; 2];
+        reader.read_exact(&mut path_len)?;
+        let mut path = vec![0; u16::from_le_bytes(path_len) as usize];
+        reader.read_exact(&mut path)?;
+
+        let entry = PackEntry {
+            path: String::from_utf8(path).map_err(|_| invalid_data("asset path is not utf-8"))?,
+            offset: read_u64(reader)?,
+            stored_len: read_u64(reader)?,
+            size: read_u64(reader)?,
+            crc32: read_u32(reader)?,
+            compressed: {
+                let mut flag = [0; 1];
+                reader.read_exact(&mut flag)?;
+                flag[0] != 0
+            },
+        };
+        if entry
+            .offset
+            .checked_add(entry.stored_len)
+            .filter(|&end| end <= pack_len)
+            .is_none()
+        {
+            return Err(invalid_data(&format!(
+                "{} lies outside the asset pack",
+                entry.path
+            )));
+        }
+        if entry.size > MAX_ENTRY_SIZE || (!entry.compressed && entry.stored_len != entry.size) {
+            return Err(invalid_data(&format!(
+                "{} has an invalid size in the asset pack",
+                entry.path
+            )));
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A whole pack in memory, laid out the way pack_assets writes it
+    fn pack(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut entries = Vec::new();
+        let mut blobs = Vec::new();
+        for (path, bytes) in files {
+            let (stored, compressed) = pack_bytes(bytes).unwrap();
+            entries.push(PackEntry {
+                path: path.to_string(),
+                offset: 0,
+                stored_len: stored.len() as u64,
+                size: bytes.len() as u64,
+                crc32: crc32fast::hash(bytes),
+                compressed,
+            });
+            blobs.push(stored);
+        }
+        let mut offset = data_offset(&entries);
+        for entry in entries.iter_mut() {
+            entry.offset = offset;
+            offset += entry.stored_len;
+        }
+        let mut pack = Vec::new();
+        write_index(&mut pack, &entries).unwrap();
+        for blob in blobs {
+            pack.extend(blob);
+        }
+        pack
+    }
+
+    fn read(pack: &[u8], entry: &PackEntry) -> io::Result<Vec<u8>> {
+        let start = entry.offset as usize;
+        unpack_bytes(
+            entry,
+            pack[start..start + entry.stored_len as usize].to_vec(),
+        )
+    }
+
+    fn files() -> Vec<(&'static str, Vec<u8>)> {
+        vec![
+            ("level1.level", b"#####\n".repeat(50)),
+            ("sprites/player.png", (0..=255).collect()),
+        ]
+    }
+
+    fn test_pack() -> Vec<u8> {
+        let files = files();
+        let files: Vec<_> = files.iter().map(|(p, b)| (*p, b.as_slice())).collect();
+        pack(&files)
+    }
+
+    #[test]
+    fn round_trip() {
+        let pack = test_pack();
+        let entries = read_index(&mut Cursor::new(&pack), pack.len() as u64).unwrap();
+        assert_eq!(entries.len(), 2);
+        // The first compresses well and the second doesn't
+        assert!(entries[0].compressed && !entries[1].compressed);
+        for (entry, (path, bytes)) in entries.iter().zip(files()) {
+            assert_eq!(entry.path, path);
+            assert_eq!(read(&pack, entry).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn corrupted_data_fails_checksum() {
+        let mut pack = test_pack();
+        let entries = read_index(&mut Cursor::new(&pack), pack.len() as u64).unwrap();
+        pack[entries[1].offset as usize] ^= 1;
+        let e = read(&pack, &entries[1]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(read(&pack, &entries[0]).is_ok());
+    }
+
+    #[test]
+    fn corrupted_index_is_rejected() {
+        let pack = test_pack();
+        let len = pack.len() as u64;
+        let corrupt = |at: usize, bytes: &[u8]| {
+            let mut pack = pack.clone();
+            pack[at..at + bytes.len()].copy_from_slice(bytes);
+            read_index(&mut Cursor::new(&pack), len).unwrap_err().kind()
+        };
+        // Fields of the first entry, after its path
+        let offset_at = (HEADER_LEN + 2) as usize + "level1.level".len();
+        let size_at = offset_at + 16;
+
+        // Entry count
+        assert_eq!(
+            corrupt(8, &u32::MAX.to_le_bytes()),
+            io::ErrorKind::InvalidData
+        );
+        // Offset and stored length past the end of the file
+        assert_eq!(
+            corrupt(offset_at, &len.to_le_bytes()),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            corrupt(offset_at + 8, &u64::MAX.to_le_bytes()),
+            io::ErrorKind::InvalidData
+        );
+        // Unpacked size
+        assert_eq!(
+            corrupt(size_at, &u64::MAX.to_le_bytes()),
+            io::ErrorKind::InvalidData
+        );
+        // The index is shorter than its count says
+        assert_eq!(
+            read_index(&mut Cursor::new(&pack[..20]), len)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
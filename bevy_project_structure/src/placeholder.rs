@@ -0,0 +1,69 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+const CHECKER_SIZE: u32 = 10;
+const DEFAULT_IMAGE_SIZE: u32 = 100;
+
+const SILENCE_SAMPLE_RATE: u32 = 8000;
+const SILENCE_LEN: f32 = 1.;
+
+// Magenta/black checkerboard, hard to miss when an image failed to load
+pub fn checker_image(size: Option<UVec2>) -> Image {
+    let size = size.unwrap_or(UVec2::splat(DEFAULT_IMAGE_SIZE));
+
+    let mut data = Vec::with_capacity((size.x * size.y * 4) as usize);
+    for y in 0..size.y {
+        for x in 0..size.x {
+            if (x / CHECKER_SIZE + y / CHECKER_SIZE) % 2 == 0 {
+                data.extend_from_slice(&[255, 0, 255, 255]);
+            } else {
+                data.extend_from_slice(&[0, 0, 0, 255]);
+            }
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+// One second of 8-bit mono silence as an in-memory WAV file
+pub fn silent_audio() -> AudioSource {
+    let data_len = (SILENCE_SAMPLE_RATE as f32 * SILENCE_LEN) as u32;
+
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk length
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // mono
+    bytes.extend_from_slice(&SILENCE_SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&SILENCE_SAMPLE_RATE.to_le_bytes()); // byte rate
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // block align
+    bytes.extend_from_slice(&8u16.to_le_bytes()); // bits per sample
+
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    // 8-bit PCM is unsigned, so silence sits at the midpoint
+    bytes.resize(44 + data_len as usize, 128);
+
+    AudioSource {
+        bytes: bytes.into(),
+    }
+}
@@ -1,27 +1,92 @@
-use bevy::{asset::LoadState, prelude::*};
+use bevy::prelude::*;
 use std::convert::From;
 
 use crate::{
-    level::Background,
-    loading::{despawn_with, LoadingAssets},
-    win::Win,
-    GameState, ACCEL_RATE, ANIM_TIME, LEVEL_LEN, PLAYER_SPEED, TILE_SIZE, WIN_H, WIN_W,
+    actions::{Action, TickActions},
+    animation::{AnimationClips, AnimationSet, Animator, Facing},
+    camera::{CameraFlash, CameraPunchZoom, CameraShake, CameraTarget},
+    campaign::GoalReached,
+    interpolation::Interpolated,
+    level::{
+        tile_to_world, world_to_tile, ActiveLevel, Background, Goal, Hazard, LevelBounds,
+        LevelSetup,
+    },
+    level_data::LevelData,
+    loading::{despawn_with, AssetsReloaded, GameAssets},
+    movement::MovementConfig,
+    sfx::PlaySfx,
+    GameState, FALL_FLASH, FALL_TRAUMA, HARD_LANDING_SPEED, LANDING_PUNCH, LANDING_TRAUMA,
+    PLAYER_SIZE, RUN_SPEED, TILE_SIZE,
 };
 
+// Shaved off the sides of the player's box (in tiles) across the way it's
+// moving, so sliding along a floor or wall it's flush against doesn't count as
+// overlapping it. Along the way it's moving only enough is taken off to allow
+// for rounding, so even a small step into a tile is caught.
+const COLLISION_SKIN: f32 = 0.01;
+const ROUNDING_SKIN: f32 = 0.0001;
+
 #[derive(Component)]
 pub struct Player;
 
-#[derive(Component, Deref, DerefMut)]
-pub struct AnimationTimer(Timer);
-
-#[derive(Component, Deref, DerefMut)]
-pub struct AnimationFrameCount(usize);
+// Shown the hurt clip after falling out of the level, until it finishes
+#[derive(Component)]
+pub struct Hurt;
 
 #[derive(Component, Deref, DerefMut)]
 pub struct Velocity(Vec2);
 
 #[derive(Resource)]
-pub struct PlayerSheet(Handle<Image>, Handle<TextureAtlasLayout>);
+pub struct PlayerSheet(
+    Handle<Image>,
+    Handle<TextureAtlasLayout>,
+    Handle<AnimationClips>,
+);
+
+const IDLE_CLIP: &str = "idle";
+const WALK_CLIP: &str = "walk";
+const RUN_CLIP: &str = "run";
+const JUMP_CLIP: &str = "jump";
+const FALL_CLIP: &str = "fall";
+const HURT_CLIP: &str = "hurt";
+// Frames of the walk and run clips where a foot comes down
+const FOOTSTEP_FRAMES: [usize; 2] = [0, 2];
+
+const FOOTSTEP_SOUND: &str = "footstep";
+const JUMP_SOUND: &str = "jump";
+const LAND_SOUND: &str = "land";
+const HURT_SOUND: &str = "hurt";
+
+// Seconds since the player was last on the ground and since jump was last pressed,
+// so a jump still works just after running off a ledge or just before landing
+#[derive(Component)]
+pub struct JumpState {
+    since_grounded: f32,
+    since_pressed: f32,
+    // Rising from a jump that can still be cut short
+    jumping: bool,
+}
+
+impl JumpState {
+    // Landed or stood on something this frame
+    pub fn grounded(&self) -> bool {
+        self.since_grounded == 0.
+    }
+}
+
+impl Default for JumpState {
+    fn default() -> Self {
+        Self {
+            since_grounded: f32::INFINITY,
+            since_pressed: f32::INFINITY,
+            jumping: false,
+        }
+    }
+}
+
+// Sent when the player falls out of the level
+#[derive(Event, Default)]
+pub struct PlayerDied;
 
 impl Velocity {
     fn new() -> Self {
@@ -38,136 +103,421 @@ impl From<Vec2> for Velocity {
 pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, load_player_sheet)
-            .add_systems(OnEnter(GameState::Playing), spawn_player)
-            .add_systems(Update, move_player.run_if(in_state(GameState::Playing)))
+        app.add_event::<PlayerDied>()
+            .add_systems(
+                Update,
+                load_player_sheet.run_if(resource_added::<GameAssets>),
+            )
+            .add_systems(OnEnter(GameState::Playing), spawn_player.after(LevelSetup))
             .add_systems(
                 Update,
-                (animate_player, move_camera)
-                    .after(move_player)
+                (load_player_sheet, sync_player_sheet)
+                    .chain()
+                    .run_if(on_event::<AssetsReloaded>())
+                    .run_if(in_state(GameState::Playing)),
+            )
+            // Gameplay runs on fixed ticks, so it plays out the same at any frame rate
+            .add_systems(
+                FixedUpdate,
+                (
+                    move_player,
+                    touch_hazards,
+                    respawn_player.run_if(on_event::<PlayerDied>()),
+                    animate_player.before(AnimationSet),
+                )
+                    .chain()
                     .run_if(in_state(GameState::Playing)),
             )
             .add_systems(OnExit(GameState::Playing), despawn_with::<Player>);
     }
 }
 
-fn load_player_sheet(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
-    mut loading_assets: ResMut<LoadingAssets>,
-) {
-    let player_sheet_handle = asset_server.load("walking.png");
-    loading_assets.push((player_sheet_handle.clone().untyped(), LoadState::NotLoaded));
-
-    let player_layout =
-        TextureAtlasLayout::from_grid(UVec2::splat(TILE_SIZE as u32), 4, 1, None, None);
-    let player_layout_handle = texture_atlases.add(player_layout);
+// The player's assets are required, so the manifest was checked for them
+fn load_player_sheet(mut commands: Commands, game_assets: Res<GameAssets>) {
+    let required = "player assets checked at load";
+    commands.insert_resource(PlayerSheet(
+        game_assets.image("player").expect(required),
+        game_assets.layout("player").expect(required),
+        game_assets.animation("player_clips").expect(required),
+    ));
+}
 
-    commands.insert_resource(PlayerSheet(player_sheet_handle, player_layout_handle));
+fn sync_player_sheet(
+    player_sheet: Res<PlayerSheet>,
+    mut player: Query<(&mut Handle<Image>, &mut TextureAtlas, &mut Animator), With<Player>>,
+) {
+    for (mut texture, mut texture_atlas, mut animator) in player.iter_mut() {
+        *texture = player_sheet.0.clone();
+        texture_atlas.layout = player_sheet.1.clone();
+        animator.clips = player_sheet.2.clone();
+    }
 }
 
 fn spawn_player(
     mut commands: Commands,
-    texture_atlases: Res<Assets<TextureAtlasLayout>>,
     player_sheet: Res<PlayerSheet>,
+    levels: Res<Assets<LevelData>>,
+    active_level: Res<ActiveLevel>,
 ) {
-    let player_layout = texture_atlases.get(&player_sheet.1);
-    let player_layout_len = player_layout.unwrap().len();
+    let spawn = tile_to_world(levels.get(&**active_level).unwrap().spawn).extend(900.);
 
     commands.spawn((
         SpriteBundle {
             texture: player_sheet.0.clone(),
-            transform: Transform::from_xyz(0., -(WIN_H / 2.) + (TILE_SIZE * 1.5), 900.),
+            transform: Transform::from_translation(spawn),
             ..default()
         },
         TextureAtlas {
             layout: player_sheet.1.clone(),
             index: 0,
         },
-        AnimationTimer(Timer::from_seconds(ANIM_TIME, TimerMode::Repeating)),
-        AnimationFrameCount(player_layout_len),
+        Animator::new(player_sheet.2.clone(), IDLE_CLIP),
+        Facing::default(),
         Velocity::new(),
+        JumpState::default(),
+        Interpolated::new(spawn),
+        CameraTarget,
         Player,
     ));
 }
 
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 fn move_player(
     time: Res<Time>,
-    input: Res<ButtonInput<KeyCode>>,
-    mut player: Query<(&mut Transform, &mut Velocity), (With<Player>, Without<Background>)>,
-    mut win_event: EventWriter<Win>,
+    actions: Res<TickActions>,
+    mut player: Query<
+        (&mut Transform, &mut Velocity, &mut JumpState),
+        (With<Player>, Without<Background>),
+    >,
+    goal: Query<&Transform, (With<Goal>, Without<Player>)>,
+    level_bounds: Res<LevelBounds>,
+    levels: Res<Assets<LevelData>>,
+    active_level: Res<ActiveLevel>,
+    movement_config: Res<MovementConfig>,
+    mut goal_reached: EventWriter<GoalReached>,
+    mut player_died: EventWriter<PlayerDied>,
+    mut camera_shake: EventWriter<CameraShake>,
+    mut camera_punch: EventWriter<CameraPunchZoom>,
+    mut sfx: EventWriter<PlaySfx>,
 ) {
-    let (mut transform, mut velocity) = player.single_mut();
+    let (mut transform, mut velocity, mut jump) = player.single_mut();
+    let level = levels.get(&**active_level).unwrap();
 
-    let mut deltav = Vec2::splat(0.);
+    // Anywhere from -1 to 1, with a stick only part way over giving part speed
+    let deltax = (actions.values.get(Action::MoveRight) - actions.values.get(Action::MoveLeft))
+        .clamp(-1., 1.);
 
-    if input.pressed(KeyCode::KeyA) {
-        deltav.x -= 1.;
+    let deltat = time.delta_seconds();
+    velocity.x = horizontal_speed(velocity.x, deltax, &movement_config, deltat);
+
+    jump.since_grounded += deltat;
+    jump.since_pressed += deltat;
+    if actions.just_pressed(Action::Jump) {
+        jump.since_pressed = 0.;
+    }
+    if jump.since_pressed <= movement_config.jump_buffer_time
+        && jump.since_grounded <= movement_config.coyote_time
+    {
+        velocity.y = movement_config.jump_speed;
+        jump.since_pressed = f32::INFINITY;
+        jump.since_grounded = f32::INFINITY;
+        jump.jumping = true;
+        sfx.send(PlaySfx::new(JUMP_SOUND));
     }
+    if jump.jumping && velocity.y <= 0. {
+        jump.jumping = false;
+    } else if jump.jumping && !actions.pressed(Action::Jump) {
+        // Let go early for a smaller hop
+        velocity.y *= movement_config.jump_cut;
+        jump.jumping = false;
+    }
+
+    velocity.y =
+        (velocity.y - movement_config.gravity * deltat).max(-movement_config.max_fall_speed);
+    let change = **velocity * deltat;
 
-    if input.pressed(KeyCode::KeyD) {
-        deltav.x += 1.;
+    // One axis at a time, so the player can slide along walls and floors
+    let pos = transform.translation.truncate();
+    let (mut new_pos, blocked) = move_axis(level, pos, Vec2::new(change.x, 0.));
+    new_pos.x = new_pos.x.clamp(
+        level_bounds.min.x + PLAYER_SIZE / 2.,
+        level_bounds.max.x - PLAYER_SIZE / 2.,
+    );
+    if blocked {
+        velocity.x = 0.;
     }
 
-    let deltat = time.delta_seconds();
-    let acc = ACCEL_RATE * deltat;
+    let (new_pos, blocked) = move_axis(level, new_pos, Vec2::new(0., change.y));
+    if blocked {
+        if change.y < 0. {
+            jump.since_grounded = 0.;
+            if velocity.y <= -HARD_LANDING_SPEED {
+                camera_shake.send(CameraShake {
+                    trauma: LANDING_TRAUMA,
+                });
+                camera_punch.send(CameraPunchZoom {
+                    amount: LANDING_PUNCH,
+                });
+                sfx.send(PlaySfx::new(LAND_SOUND));
+            }
+        }
+        velocity.y = 0.;
+    }
+    transform.translation = new_pos.extend(transform.translation.z);
+
+    if new_pos.y < level_bounds.min.y - PLAYER_SIZE {
+        player_died.send_default();
+        return;
+    }
+
+    let gt = goal.single();
+    if (transform.translation.truncate() - gt.translation.truncate())
+        .abs()
+        .cmplt(Vec2::splat(TILE_SIZE))
+        .all()
+    {
+        // Touching the goal, on to the next level
+        goal_reached.send(GoalReached);
+    }
+}
+
+fn touch_hazards(
+    player: Query<&Transform, With<Player>>,
+    hazards: Query<&Transform, (With<Hazard>, Without<Player>)>,
+    mut player_died: EventWriter<PlayerDied>,
+) {
+    let pt = player.single();
+    // Only overlapping counts, not standing flush against one
+    let reach = Vec2::splat((PLAYER_SIZE + TILE_SIZE) / 2.);
+    if hazards.iter().any(|ht| {
+        (pt.translation.truncate() - ht.translation.truncate())
+            .abs()
+            .cmplt(reach)
+            .all()
+    }) {
+        player_died.send_default();
+    }
+}
 
-    **velocity = if deltav.length() > 0. {
-        (**velocity + (deltav.normalize_or_zero() * acc)).clamp_length_max(PLAYER_SPEED)
-    } else if velocity.length() > acc {
-        **velocity + (velocity.normalize_or_zero() * -acc)
+// Heads for the speed asked for by `input`. Pushing against the way the player
+// is going brakes at the turn-around rate, and letting go (or easing off a
+// stick) slows down at the deceleration rate.
+fn horizontal_speed(speed: f32, input: f32, movement_config: &MovementConfig, deltat: f32) -> f32 {
+    let target = input * movement_config.max_speed;
+    let rate = if speed * input < 0. {
+        movement_config.turn_rate
+    } else if input != 0. && speed.abs() < target.abs() {
+        movement_config.acceleration
     } else {
-        Vec2::splat(0.)
+        movement_config.deceleration
     };
-    let change = **velocity * deltat;
 
-    let new_pos = transform.translation + Vec3::new(change.x, 0., 0.);
-    if new_pos.x >= -(WIN_W / 2.) + TILE_SIZE / 2.
-        && new_pos.x <= LEVEL_LEN - (WIN_W / 2. + TILE_SIZE / 2.)
-    {
-        transform.translation = new_pos;
+    let step = rate * deltat;
+    if (target - speed).abs() <= step {
+        target
+    } else {
+        speed + (target - speed).signum() * step
     }
+}
+
+// Back to the start of the level
+fn respawn_player(
+    mut commands: Commands,
+    mut player: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Interpolated,
+            &mut Velocity,
+            &mut JumpState,
+        ),
+        With<Player>,
+    >,
+    levels: Res<Assets<LevelData>>,
+    active_level: Res<ActiveLevel>,
+    mut camera_shake: EventWriter<CameraShake>,
+    mut camera_flash: EventWriter<CameraFlash>,
+    mut sfx: EventWriter<PlaySfx>,
+) {
+    let (entity, mut transform, mut interpolated, mut velocity, mut jump) = player.single_mut();
+    let spawn = tile_to_world(levels.get(&**active_level).unwrap().spawn);
 
-    let new_pos = transform.translation + Vec3::new(0., change.y, 0.);
-    if new_pos.y >= -(WIN_H / 2.) + (TILE_SIZE * 1.5) && new_pos.y <= WIN_H / 2. - TILE_SIZE / 2. {
-        transform.translation = new_pos;
+    info!("Player died, back to the start");
+    transform.translation = spawn.extend(transform.translation.z);
+    interpolated.snap(transform.translation);
+    **velocity = Vec2::ZERO;
+    *jump = JumpState::default();
+    commands.entity(entity).insert(Hurt);
+    camera_shake.send(CameraShake {
+        trauma: FALL_TRAUMA,
+    });
+    camera_flash.send(CameraFlash { color: FALL_FLASH });
+    sfx.send(PlaySfx::new(HURT_SOUND));
+}
+
+// The player's box in tile units, where tile (x, y) covers x..x + 1 and y..y + 1
+fn player_box(pos: Vec2) -> Rect {
+    let centre = world_to_tile(pos) + Vec2::splat(0.5);
+    Rect::from_center_size(centre, Vec2::splat(PLAYER_SIZE / TILE_SIZE))
+}
+
+fn hits_solid(level: &LevelData, pos: Vec2, change: Vec2) -> bool {
+    let skin = if change.x != 0. {
+        Vec2::new(ROUNDING_SKIN, COLLISION_SKIN)
+    } else {
+        Vec2::new(COLLISION_SKIN, ROUNDING_SKIN)
+    };
+    let bounds = player_box(pos);
+    let bounds = Rect::from_corners(bounds.min + skin, bounds.max - skin);
+    let min = bounds.min.floor().as_ivec2();
+    let max = bounds.max.ceil().as_ivec2();
+    (min.y..max.y).any(|y| (min.x..max.x).any(|x| level.is_solid(x, y)))
+}
+
+// Moves along a single axis. A blocked move stops flush against the tile in
+// the way rather than short of it, and says it was blocked.
+fn move_axis(level: &LevelData, pos: Vec2, change: Vec2) -> (Vec2, bool) {
+    let new_pos = pos + change;
+    if !hits_solid(level, new_pos, change) {
+        return (new_pos, false);
     }
 
-    if new_pos.x > LEVEL_LEN - (WIN_W / 2. + TILE_SIZE) {
-        // Close enough to end of level, move to WinScreen
-        win_event.send(Win);
+    let bounds = player_box(new_pos);
+    let overlap = if change.x > 0. {
+        Vec2::new(bounds.max.x.floor() - bounds.max.x, 0.)
+    } else if change.x < 0. {
+        Vec2::new(bounds.min.x.ceil() - bounds.min.x, 0.)
+    } else if change.y > 0. {
+        Vec2::new(0., bounds.max.y.floor() - bounds.max.y)
+    } else {
+        Vec2::new(0., bounds.min.y.ceil() - bounds.min.y)
+    };
+    let flush = new_pos + overlap * TILE_SIZE;
+    // A long enough step could also have passed through a tile before the one hit
+    if hits_solid(level, flush, change) {
+        (pos, true)
+    } else {
+        (flush, true)
     }
 }
 
+// Picks the clip and facing from what the player is doing
+#[allow(clippy::type_complexity)]
 fn animate_player(
-    time: Res<Time>,
+    mut commands: Commands,
     mut player: Query<
         (
+            Entity,
             &Velocity,
-            &mut TextureAtlas,
-            &mut AnimationTimer,
-            &AnimationFrameCount,
+            &JumpState,
+            &mut Animator,
+            &mut Facing,
+            Has<Hurt>,
         ),
         With<Player>,
     >,
+    mut sfx: EventWriter<PlaySfx>,
+    // Frame of the walk or run clip last time, so each step is only heard once
+    mut last_step_frame: Local<Option<usize>>,
 ) {
-    let (velocity, mut texture_atlas, mut timer, frame_count) = player.single_mut();
-    if velocity.cmpne(Vec2::ZERO).any() {
-        timer.tick(time.delta());
+    let (entity, velocity, jump, mut animator, mut facing, hurt) = player.single_mut();
 
-        if timer.just_finished() {
-            texture_atlas.index = (texture_atlas.index + 1) % **frame_count;
+    if velocity.x < 0. {
+        facing.set_if_neq(Facing::Left);
+    } else if velocity.x > 0. {
+        facing.set_if_neq(Facing::Right);
+    }
+
+    if hurt {
+        if animator.clip() != HURT_CLIP || !animator.finished() {
+            animator.play(HURT_CLIP);
+            return;
+        }
+        commands.entity(entity).remove::<Hurt>();
+    }
+
+    let clip = if !jump.grounded() {
+        if velocity.y > 0. {
+            JUMP_CLIP
+        } else {
+            FALL_CLIP
+        }
+    } else if velocity.x.abs() > RUN_SPEED {
+        RUN_CLIP
+    } else if velocity.x != 0. {
+        WALK_CLIP
+    } else {
+        IDLE_CLIP
+    };
+    animator.play(clip);
+
+    if clip == WALK_CLIP || clip == RUN_CLIP {
+        let frame = animator.frame();
+        if *last_step_frame != Some(frame) && FOOTSTEP_FRAMES.contains(&frame) {
+            sfx.send(PlaySfx::new(FOOTSTEP_SOUND));
         }
+        *last_step_frame = Some(frame);
+    } else {
+        *last_step_frame = None;
     }
 }
 
-fn move_camera(
-    player: Query<&Transform, With<Player>>,
-    mut camera: Query<&mut Transform, (Without<Player>, With<Camera>)>,
-) {
-    let pt = player.single();
-    let mut ct = camera.single_mut();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 1. / 60.;
 
-    ct.translation.x = pt.translation.x.clamp(0., LEVEL_LEN - WIN_W);
+    fn run(mut speed: f32, input: f32, frames: usize) -> f32 {
+        let movement_config = MovementConfig::default();
+        for _ in 0..frames {
+            speed = horizontal_speed(speed, input, &movement_config, STEP);
+        }
+        speed
+    }
+
+    #[test]
+    fn half_tilt_settles_at_half_speed() {
+        let max_speed = MovementConfig::default().max_speed;
+        assert_eq!(run(0., 0.5, 120), max_speed / 2.);
+        assert_eq!(run(0., -1., 120), -max_speed);
+    }
+
+    #[test]
+    fn easing_off_slows_to_new_target() {
+        let max_speed = MovementConfig::default().max_speed;
+        assert_eq!(run(max_speed, 0.25, 120), max_speed / 4.);
+        assert_eq!(run(max_speed, 0., 120), 0.);
+    }
+
+    // A floor along the bottom row and a wall at column 3
+    fn walled_level() -> LevelData {
+        LevelData::parse(
+            "tileset = bricks\nbackground = background\ntile # = 0\nmap:\nP  #G\n#####",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn small_step_into_a_tile_is_blocked() {
+        let level = walled_level();
+        // Standing on the floor, flush against the wall
+        let flush = tile_to_world(Vec2::new(2., 1.));
+        for change in [Vec2::new(0.5, 0.), Vec2::new(30., 0.), Vec2::new(0., -0.5)] {
+            let (pos, blocked) = move_axis(&level, flush, change);
+            assert!(blocked, "{:?} wasn't blocked", change);
+            assert!(pos.distance(flush) < 0.01, "{:?} went to {:?}", change, pos);
+        }
+        // Sliding along the floor and the wall still works
+        let start = tile_to_world(Vec2::new(0., 1.));
+        assert!(!move_axis(&level, start, Vec2::new(10., 0.)).1);
+        assert!(!move_axis(&level, flush, Vec2::new(0., 10.)).1);
+    }
+
+    #[test]
+    fn turning_uses_turn_rate() {
+        let movement_config = MovementConfig::default();
+        let speed = horizontal_speed(100., -1., &movement_config, STEP);
+        assert_eq!(speed, 100. - movement_config.turn_rate * STEP);
+    }
 }
@@ -0,0 +1,398 @@
+use bevy::{app::FixedMain, input::common_conditions::input_just_pressed, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fs};
+
+use crate::{
+    actions::{ActionSet, TickActions, TickInput},
+    campaign::CurrentLevel,
+    interpolation::InterpolationSet,
+    levelgen::LevelSeed,
+    movement::{LockedMovementConfig, MovementConfig},
+    GameState, REPLAY_FAST_FORWARD,
+};
+
+// One input and how many ticks in a row it lasted
+type InputRun = (u32, TickInput);
+
+// Everything needed to play a session back exactly: where it started and the
+// input on every tick of gameplay
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Replay {
+    // Seed for a generated level, if it was one
+    pub seed: Option<u64>,
+    // Index into the campaign of the first level played
+    pub level: usize,
+    // Tuning the player moved with, as the config file may differ where it's
+    // played back. Older recordings don't have it.
+    #[serde(default)]
+    pub movement_config: Option<MovementConfig>,
+    // Each level's input separately, as a level doesn't end on the same tick it's
+    // finished (that depends on when the frame falls), so the number of ticks
+    // after touching the goal can differ between runs
+    pub levels: Vec<Vec<InputRun>>,
+}
+
+impl Replay {
+    fn push(&mut self, input: TickInput) {
+        let Some(ticks) = self.levels.last_mut() else {
+            return;
+        };
+        match ticks.last_mut() {
+            Some((count, last)) if *last == input => *count += 1,
+            _ => ticks.push((1, input)),
+        }
+    }
+}
+
+pub fn load_replay(path: &str) -> Result<Replay, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    ron::from_str(&text).map_err(|e| e.to_string())
+}
+
+// Saves the player's input to a file as they play
+#[derive(Resource)]
+struct Recorder {
+    path: String,
+    replay: Replay,
+}
+
+// Plays a recording back in place of the player's input
+#[derive(Resource)]
+pub struct Playback {
+    levels: VecDeque<Vec<InputRun>>,
+    // What's left of the level being played
+    ticks: VecDeque<InputRun>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            levels: replay.levels.into(),
+            ticks: VecDeque::new(),
+        }
+    }
+}
+
+const PAUSE_KEY: KeyCode = KeyCode::KeyP;
+const STEP_KEY: KeyCode = KeyCode::KeyN;
+const FAST_FORWARD_KEY: KeyCode = KeyCode::KeyF;
+
+// Either records the session to `record`, or plays back `replay`
+pub struct ReplayPlugin {
+    pub record: Option<String>,
+    pub replay: Option<Replay>,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(replay) = &self.replay {
+            info!(
+                "Playing back a recording: {:?} pauses, {:?} steps while paused, {:?} fast-forwards",
+                PAUSE_KEY, STEP_KEY, FAST_FORWARD_KEY
+            );
+            if let Some(movement_config) = &replay.movement_config {
+                app.insert_resource(movement_config.clone())
+                    .insert_resource(LockedMovementConfig);
+            }
+            app.insert_resource(CurrentLevel(replay.level))
+                .insert_resource(Playback::new(replay.clone()))
+                .add_systems(OnEnter(GameState::MainMenu), skip_main_menu)
+                .add_systems(OnEnter(GameState::Playing), start_playback_level)
+                .add_systems(
+                    FixedPreUpdate,
+                    play_tick
+                        .after(ActionSet)
+                        .run_if(in_state(GameState::Playing)),
+                )
+                .add_systems(
+                    Update,
+                    (
+                        playback_controls,
+                        step_tick.run_if(input_just_pressed(STEP_KEY)),
+                    )
+                        .chain()
+                        .before(InterpolationSet),
+                );
+        } else if let Some(path) = &self.record {
+            info!("Recording input to {}", path);
+            app.insert_resource(Recorder {
+                path: path.clone(),
+                replay: Replay::default(),
+            })
+            .add_systems(OnEnter(GameState::Playing), start_recording_level)
+            .add_systems(
+                FixedPreUpdate,
+                record_tick
+                    .after(ActionSet)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnEnter(GameState::Win), save_recording)
+            .add_systems(Last, save_recording.run_if(on_event::<AppExit>()));
+        }
+    }
+}
+
+fn start_recording_level(
+    mut recorder: ResMut<Recorder>,
+    current_level: Res<CurrentLevel>,
+    seed: Option<Res<LevelSeed>>,
+    movement_config: Res<MovementConfig>,
+) {
+    let replay = &mut recorder.replay;
+    if replay.levels.is_empty() {
+        replay.level = **current_level;
+        replay.seed = seed.map(|seed| **seed);
+        replay.movement_config = Some(movement_config.clone());
+    }
+    replay.levels.push(Vec::new());
+}
+
+fn record_tick(mut recorder: ResMut<Recorder>, tick_actions: Res<TickActions>) {
+    recorder.replay.push(tick_actions.input());
+}
+
+fn save_recording(recorder: Res<Recorder>) {
+    let result = ron::to_string(&recorder.replay)
+        .map_err(|e| e.to_string())
+        .and_then(|text| fs::write(&recorder.path, text).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => info!("Saved recording to {}", recorder.path),
+        Err(e) => warn!("Could not save recording to {}: {}", recorder.path, e),
+    }
+}
+
+// A recording starts straight away, on the level it was recorded from
+fn skip_main_menu(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Loading);
+}
+
+fn start_playback_level(mut playback: ResMut<Playback>) {
+    playback.ticks = playback.levels.pop_front().unwrap_or_default().into();
+}
+
+fn play_tick(mut playback: ResMut<Playback>, mut tick_actions: ResMut<TickActions>) {
+    // Once the recording runs out, the player just stands there
+    let input = match playback.ticks.front_mut() {
+        Some((count, input)) => {
+            let input = *input;
+            *count -= 1;
+            if *count == 0 {
+                playback.ticks.pop_front();
+            }
+            input
+        }
+        None => TickInput::default(),
+    };
+    tick_actions.set_input(&input);
+}
+
+fn playback_controls(keys: Res<ButtonInput<KeyCode>>, mut time: ResMut<Time<Virtual>>) {
+    if keys.just_pressed(PAUSE_KEY) {
+        if time.is_paused() {
+            time.unpause();
+        } else {
+            time.pause();
+        }
+    }
+    if keys.just_pressed(FAST_FORWARD_KEY) {
+        let speed = if time.relative_speed() == 1. {
+            REPLAY_FAST_FORWARD
+        } else {
+            1.
+        };
+        time.set_relative_speed(speed);
+    }
+}
+
+// While paused, runs a single fixed tick the way the fixed loop would
+fn step_tick(world: &mut World) {
+    if !world.resource::<Time<Virtual>>().is_paused() {
+        return;
+    }
+    let mut fixed = *world.resource::<Time<Fixed>>();
+    let timestep = fixed.timestep();
+    fixed.advance_by(timestep);
+    world.insert_resource(fixed);
+    *world.resource_mut::<Time>() = fixed.as_generic();
+    world.run_schedule(FixedMain);
+    *world.resource_mut::<Time>() = world.resource::<Time<Virtual>>().as_generic();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        add_game,
+        campaign::GoalReached,
+        player::{Player, Velocity},
+        FIXED_HZ,
+    };
+    use bevy::{
+        render::{settings::WgpuSettings, RenderPlugin},
+        time::TimeUpdateStrategy,
+        winit::WinitPlugin,
+    };
+    use std::{thread, time::Duration};
+
+    // Recorded at 60 fps: the whole campaign from the first level, and a
+    // generated level with plenty of jumps
+    const RUNS: [&str; 2] = ["tests/campaign.replay.ron", "tests/seed_1.replay.ron"];
+
+    // The whole game with no window or GPU, moving time on by 1 / fps each frame
+    fn headless_game(replay: Replay, fps: f64) -> App {
+        let mut app = App::new();
+        app.add_plugins(
+            DefaultPlugins
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }
+                    .into(),
+                    ..default()
+                })
+                .disable::<WinitPlugin>(),
+        )
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1. / fps,
+        )));
+        // No need to hold the loading screen up, nobody is watching
+        add_game(&mut app, replay.seed, 0.);
+        app.add_plugins(ReplayPlugin {
+            record: None,
+            replay: Some(replay),
+        });
+        // Normally done by App::run, and needed to register the image loader
+        app.finish();
+        app.cleanup();
+        app
+    }
+
+    fn reaches_win(app: &mut App, max_frames: usize) -> bool {
+        for _ in 0..max_frames {
+            app.update();
+            if *app.world().resource::<State<GameState>>().get() == GameState::Win {
+                return true;
+            }
+            // Give the asset loaders a moment to read files
+            thread::sleep(Duration::from_micros(200));
+        }
+        false
+    }
+
+    #[test]
+    fn recorded_run_wins_at_any_frame_rate() {
+        for (run, fps) in RUNS
+            .into_iter()
+            .flat_map(|run| [(run, FIXED_HZ), (run, 144.), (run, 30.)])
+        {
+            let replay = load_replay(run).unwrap();
+            let mut app = headless_game(replay, fps);
+            assert!(
+                reaches_win(&mut app, 30_000),
+                "{} didn't win at {} fps",
+                run,
+                fps
+            );
+        }
+    }
+
+    // The player's simulated translation and velocity as bits, on every tick of
+    // each level up to the one it touched the goal on
+    #[derive(Resource, Default)]
+    struct Trajectory {
+        levels: Vec<Vec<[u32; 5]>>,
+        finished: bool,
+    }
+
+    fn start_trajectory(mut trajectory: ResMut<Trajectory>) {
+        trajectory.levels.push(Vec::new());
+        trajectory.finished = false;
+    }
+
+    fn record_trajectory(
+        mut trajectory: ResMut<Trajectory>,
+        player: Query<(&Transform, &Velocity), With<Player>>,
+        mut goal_reached: EventReader<GoalReached>,
+    ) {
+        let Ok((transform, velocity)) = player.get_single() else {
+            return;
+        };
+        if trajectory.finished {
+            return;
+        }
+        let [x, y, z] = transform.translation.to_array();
+        let state = [x, y, z, velocity.x, velocity.y].map(f32::to_bits);
+        trajectory.levels.last_mut().unwrap().push(state);
+        trajectory.finished = goal_reached.read().count() > 0;
+    }
+
+    #[test]
+    fn recorded_run_is_bit_identical_at_any_frame_rate() {
+        for run in RUNS {
+            let trajectories: Vec<_> = [FIXED_HZ, 144., 30.]
+                .into_iter()
+                .map(|fps| {
+                    let mut app = headless_game(load_replay(run).unwrap(), fps);
+                    app.init_resource::<Trajectory>()
+                        .add_systems(OnEnter(GameState::Playing), start_trajectory)
+                        .add_systems(
+                            FixedLast,
+                            record_trajectory.run_if(in_state(GameState::Playing)),
+                        );
+                    assert!(
+                        reaches_win(&mut app, 30_000),
+                        "{} didn't win at {} fps",
+                        run,
+                        fps
+                    );
+                    app.world_mut()
+                        .remove_resource::<Trajectory>()
+                        .unwrap()
+                        .levels
+                })
+                .collect();
+            assert!(trajectories[0].iter().all(|level| !level.is_empty()));
+            for (trajectory, fps) in trajectories[1..].iter().zip([144, 30]) {
+                assert!(
+                    trajectory == &trajectories[0],
+                    "{} at {} fps differs from {} fps",
+                    run,
+                    fps,
+                    FIXED_HZ
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn playback_keeps_recorded_tuning() {
+        let movement_config = MovementConfig {
+            jump_speed: 1000.,
+            ..default()
+        };
+        let replay = Replay {
+            movement_config: Some(movement_config.clone()),
+            ..load_replay(RUNS[0]).unwrap()
+        };
+        let mut app = headless_game(replay, FIXED_HZ);
+        // Long enough for the config file to have loaded
+        for _ in 0..60 {
+            app.update();
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(app.world().resource::<MovementConfig>(), &movement_config);
+    }
+
+    #[test]
+    fn repeated_input_is_stored_once() {
+        let mut replay = Replay::default();
+        replay.levels.push(Vec::new());
+        let held = load_replay(RUNS[0]).unwrap().levels[0][0].1;
+        for _ in 0..3 {
+            replay.push(held);
+        }
+        replay.push(TickInput::default());
+        assert_eq!(replay.levels[0], vec![(3, held), (1, TickInput::default())]);
+    }
+}
@@ -0,0 +1,82 @@
+use bevy::{
+    prelude::*,
+    window::{PrimaryWindow, WindowMode},
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::SETTINGS_PATH;
+
+// Player preferences, saved between runs
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    // Volume buses from 0 to 1. Music and sound effects are both also scaled by master.
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    // How strong screen shake and punch zooms are, from 0 (off) to 1
+    pub screen_shake: f32,
+    // Whether the screen flashes on hits
+    pub flashes: bool,
+    pub fullscreen: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.,
+            music_volume: 1.,
+            sfx_volume: 1.,
+            screen_shake: 1.,
+            flashes: true,
+            fullscreen: false,
+        }
+    }
+}
+
+pub struct SettingsPlugin;
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_settings()).add_systems(
+            Update,
+            apply_window_mode.run_if(resource_changed::<Settings>),
+        );
+    }
+}
+
+fn load_settings() -> Settings {
+    // Only written once something is changed in the settings menu
+    let Ok(text) = fs::read_to_string(SETTINGS_PATH) else {
+        return Settings::default();
+    };
+    ron::from_str(&text).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable settings {}: {}", SETTINGS_PATH, e);
+        Settings::default()
+    })
+}
+
+pub fn save_settings(settings: &Settings) {
+    let result = ron::ser::to_string_pretty(settings, default())
+        .map_err(|e| e.to_string())
+        .and_then(|text| fs::write(SETTINGS_PATH, text).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        warn!("Could not save settings to {}: {}", SETTINGS_PATH, e);
+    }
+}
+
+fn apply_window_mode(
+    settings: Res<Settings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let mode = if settings.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    };
+    for mut window in windows.iter_mut() {
+        if window.mode != mode {
+            window.mode = mode;
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    audio::{PlaybackMode, Volume},
+    prelude::*,
+    utils::HashMap,
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{loading::GameAssets, settings::Settings, SFX_BANK, SFX_VOICE_LIMIT};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Sound {
+    // Audio keys in the asset manifest, one picked at random each time
+    pub variants: Vec<String>,
+    #[serde(default = "full_volume")]
+    pub volume: f32,
+    // Volume and pitch vary by up to this fraction either way
+    #[serde(default)]
+    pub volume_variation: f32,
+    #[serde(default)]
+    pub pitch_variation: f32,
+    // Seconds before the sound can play again
+    #[serde(default)]
+    pub cooldown: f32,
+}
+
+fn full_volume() -> f32 {
+    1.
+}
+
+// Sounds by id, for example:
+//
+//   {
+//       "jump": (variants: ["jump"], pitch_variation: 0.05),
+//       "footstep": (variants: ["step1", "step2"], cooldown: 0.1),
+//   }
+#[derive(Asset, TypePath, Deserialize, Debug, Deref)]
+#[serde(transparent)]
+pub struct SoundBank(HashMap<String, Sound>);
+
+#[derive(Default)]
+pub struct SoundBankLoader;
+
+#[derive(Error, Debug)]
+pub enum SoundBankLoaderError {
+    #[error("Could not read sound bank: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse sound bank: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for SoundBankLoader {
+    type Asset = SoundBank;
+    type Settings = ();
+    type Error = SoundBankLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sounds.ron"]
+    }
+}
+
+// Plays the sound with this id from the sound bank
+#[derive(Event)]
+pub struct PlaySfx(pub String);
+
+impl PlaySfx {
+    pub fn new(id: &str) -> Self {
+        Self(id.to_string())
+    }
+}
+
+// One sound to start, after variation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Voice {
+    pub audio: String,
+    pub volume: f32,
+    pub speed: f32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Skipped {
+    UnknownSound,
+    Cooldown,
+    VoiceLimit,
+}
+
+// Decides what actually plays for each request, apart from the audio itself
+#[derive(Resource)]
+pub struct SfxMixer {
+    rng: ChaCha8Rng,
+    // When each sound last played, in seconds
+    last_played: HashMap<String, f32>,
+}
+
+impl SfxMixer {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            last_played: HashMap::new(),
+        }
+    }
+
+    // `now` is the time in seconds, and `voices` how many sounds are already playing
+    pub fn choose(
+        &mut self,
+        bank: &SoundBank,
+        id: &str,
+        now: f32,
+        voices: usize,
+    ) -> Result<Voice, Skipped> {
+        let sound = bank
+            .get(id)
+            .filter(|sound| !sound.variants.is_empty())
+            .ok_or(Skipped::UnknownSound)?;
+        if self
+            .last_played
+            .get(id)
+            .is_some_and(|&last| now - last < sound.cooldown)
+        {
+            return Err(Skipped::Cooldown);
+        }
+        if voices >= SFX_VOICE_LIMIT {
+            return Err(Skipped::VoiceLimit);
+        }
+        self.last_played.insert(id.to_string(), now);
+
+        let audio = sound.variants[self.rng.gen_range(0..sound.variants.len())].clone();
+        let volume = sound.volume * (1. + self.vary(sound.volume_variation));
+        let speed = 1. + self.vary(sound.pitch_variation);
+        Ok(Voice {
+            audio,
+            volume: volume.max(0.),
+            speed: speed.max(0.01),
+        })
+    }
+
+    // Anywhere from -amount to amount
+    fn vary(&mut self, amount: f32) -> f32 {
+        if amount > 0. {
+            self.rng.gen_range(-amount..=amount)
+        } else {
+            0.
+        }
+    }
+}
+
+// A sound effect that's playing, at its volume before settings are applied
+#[derive(Component)]
+struct SfxVoice(f32);
+
+pub struct SfxPlugin;
+impl Plugin for SfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<SoundBank>()
+            .init_asset_loader::<SoundBankLoader>()
+            .add_event::<PlaySfx>()
+            .insert_resource(SfxMixer::new(rand::random()))
+            .add_systems(
+                Update,
+                (
+                    check_sound_banks.run_if(resource_exists::<GameAssets>),
+                    play_sfx.run_if(resource_exists::<GameAssets>),
+                    set_sfx_volume.run_if(resource_changed::<Settings>),
+                ),
+            );
+    }
+}
+
+// Variants are typed by hand, so catch ones missing from the manifest as soon
+// as a bank loads or is edited, rather than each time they'd play
+fn check_sound_banks(
+    game_assets: Res<GameAssets>,
+    banks: Res<Assets<SoundBank>>,
+    mut events: EventReader<AssetEvent<SoundBank>>,
+) {
+    for event in events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event
+        else {
+            continue;
+        };
+        let Some(bank) = banks.get(*id) else {
+            continue;
+        };
+        for (sound, variant) in bank
+            .iter()
+            .flat_map(|(sound, s)| s.variants.iter().map(move |v| (sound, v)))
+        {
+            if game_assets.audio(variant).is_none() {
+                warn!(
+                    "Sound {:?} has variant {:?}, which is not audio in the asset manifest",
+                    sound, variant
+                );
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn play_sfx(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_assets: Res<GameAssets>,
+    banks: Res<Assets<SoundBank>>,
+    settings: Res<Settings>,
+    mut mixer: ResMut<SfxMixer>,
+    mut requests: EventReader<PlaySfx>,
+    voices: Query<(), With<SfxVoice>>,
+) {
+    // The bank may be missing from the manifest, or dropped with its group
+    let Some(bank) = game_assets
+        .sound_bank(SFX_BANK)
+        .and_then(|handle| banks.get(&handle))
+    else {
+        requests.clear();
+        return;
+    };
+    // Counts what's started this frame too, as those aren't spawned yet
+    let mut playing = voices.iter().count();
+    for PlaySfx(id) in requests.read() {
+        let voice = match mixer.choose(bank, id, time.elapsed_seconds(), playing) {
+            Ok(voice) => voice,
+            Err(Skipped::UnknownSound) => {
+                warn!("No sound {:?} in the sound bank", id);
+                continue;
+            }
+            Err(_) => continue,
+        };
+        // Already warned about when the bank loaded
+        let Some(source) = game_assets.audio(&voice.audio) else {
+            continue;
+        };
+        playing += 1;
+        commands.spawn((
+            AudioBundle {
+                source,
+                settings: PlaybackSettings {
+                    mode: PlaybackMode::Despawn,
+                    volume: Volume::new(
+                        voice.volume * settings.master_volume * settings.sfx_volume,
+                    ),
+                    speed: voice.speed,
+                    ..default()
+                },
+            },
+            SfxVoice(voice.volume),
+        ));
+    }
+}
+
+fn set_sfx_volume(settings: Res<Settings>, voices: Query<(&SfxVoice, &AudioSink)>) {
+    for (voice, sink) in voices.iter() {
+        sink.set_volume(voice.0 * settings.master_volume * settings.sfx_volume);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bank() -> SoundBank {
+        ron::from_str(
+            r#"{
+                "step": (variants: ["step1", "step2", "step3"], volume: 0.5,
+                    volume_variation: 0.2, pitch_variation: 0.1, cooldown: 0.1),
+                "jump": (variants: ["jump"]),
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn variation_stays_in_range() {
+        let bank = bank();
+        let mut mixer = SfxMixer::new(1);
+        let mut heard = Vec::new();
+        for i in 0..200 {
+            let voice = mixer.choose(&bank, "step", i as f32, 0).unwrap();
+            assert!((0.4 - f32::EPSILON..=0.6 + f32::EPSILON).contains(&voice.volume));
+            assert!((0.9 - f32::EPSILON..=1.1 + f32::EPSILON).contains(&voice.speed));
+            if !heard.contains(&voice.audio) {
+                heard.push(voice.audio);
+            }
+        }
+        assert_eq!(heard.len(), 3);
+
+        // No variation asked for
+        let voice = mixer.choose(&bank, "jump", 0., 0).unwrap();
+        assert_eq!((voice.volume, voice.speed), (1., 1.));
+    }
+
+    #[test]
+    fn cooldown_is_per_sound() {
+        let bank = bank();
+        let mut mixer = SfxMixer::new(1);
+        assert!(mixer.choose(&bank, "step", 1., 0).is_ok());
+        assert_eq!(mixer.choose(&bank, "step", 1.05, 0), Err(Skipped::Cooldown));
+        assert!(mixer.choose(&bank, "jump", 1.05, 0).is_ok());
+        assert!(mixer.choose(&bank, "step", 1.1, 0).is_ok());
+    }
+
+    #[test]
+    fn voice_limit_and_unknown_sounds_skip() {
+        let bank = bank();
+        let mut mixer = SfxMixer::new(1);
+        assert_eq!(
+            mixer.choose(&bank, "jump", 0., SFX_VOICE_LIMIT),
+            Err(Skipped::VoiceLimit)
+        );
+        // A skipped sound doesn't start its cooldown
+        assert!(mixer.choose(&bank, "jump", 0., SFX_VOICE_LIMIT - 1).is_ok());
+        assert_eq!(
+            mixer.choose(&bank, "explosion", 0., 0),
+            Err(Skipped::UnknownSound)
+        );
+    }
+
+    #[test]
+    fn same_seed_same_choices() {
+        let bank = bank();
+        let (mut a, mut b) = (SfxMixer::new(7), SfxMixer::new(7));
+        for i in 0..20 {
+            let now = i as f32;
+            assert_eq!(
+                a.choose(&bank, "step", now, 0),
+                b.choose(&bank, "step", now, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn missing_bank_drops_requests() {
+        // A manifest without the bank, so nothing can be played
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<SoundBank>()
+            .add_event::<PlaySfx>()
+            .insert_resource(SfxMixer::new(1))
+            .init_resource::<GameAssets>()
+            .init_resource::<Settings>()
+            .add_systems(Update, play_sfx);
+
+        app.world_mut().send_event(PlaySfx("jump".to_string()));
+        app.update();
+        let mut voices = app.world_mut().query::<&SfxVoice>();
+        assert_eq!(voices.iter(app.world()).count(), 0);
+    }
+}
@@ -0,0 +1,752 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, ReadAssetBytesError},
+    prelude::*,
+};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use serde::Deserialize;
+use std::io::Read;
+use thiserror::Error;
+
+use crate::level_data::{LevelData, LevelObject, Properties, PropertyValue, TileLayer};
+
+// Tiled stores tile flips in the top bits of each gid
+const FLIP_FLAGS: u32 = 0xF000_0000;
+
+// Loads maps saved by Tiled as JSON (.tmj) or XML (.tmx).
+//
+// Each tileset's name is the manifest key of the atlas its tiles are drawn
+// from, and tile ids within a tileset are atlas indices. Objects use their
+// class (or type, in older versions of Tiled) as their kind. The map needs a
+// string property "background" naming the background image.
+#[derive(Default)]
+pub struct TiledLoader;
+
+#[derive(Error, Debug)]
+pub enum TiledLoaderError {
+    #[error("Could not read map: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not read tileset: {0}")]
+    Tileset(#[from] ReadAssetBytesError),
+    #[error("Could not parse map: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Could not parse map: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("Invalid map, {0}")]
+    Invalid(String),
+}
+
+impl AssetLoader for TiledLoader {
+    type Asset = LevelData;
+    type Settings = ();
+    type Error = TiledLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let is_xml = load_context
+            .path()
+            .extension()
+            .is_some_and(|ext| ext == "tmx");
+        let mut map = if is_xml {
+            let text = String::from_utf8(bytes).map_err(|e| invalid(e.to_string()))?;
+            parse_tmx(&roxmltree::Document::parse(&text)?)?
+        } else {
+            parse_tmj(serde_json::from_slice(&bytes)?)?
+        };
+
+        // External tilesets live in their own file, which only needs reading for the name
+        for tileset in map.tilesets.iter_mut() {
+            let Some(source) = tileset.source.take() else {
+                continue;
+            };
+            let path = load_context
+                .asset_path()
+                .resolve_embed(&source)
+                .map_err(|e| invalid(e.to_string()))?;
+            let bytes = load_context.read_asset_bytes(path).await?;
+            tileset.name = external_tileset_name(&source, bytes)?;
+        }
+
+        map.into_level_data()
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmj", "tmx"]
+    }
+}
+
+// From a .tsx or .tsj tileset file
+fn external_tileset_name(source: &str, bytes: Vec<u8>) -> Result<String, TiledLoaderError> {
+    if source.ends_with(".tsx") {
+        let text = String::from_utf8(bytes).map_err(|e| invalid(e.to_string()))?;
+        let doc = roxmltree::Document::parse(&text)?;
+        Ok(attr(doc.root_element(), "name")?.to_string())
+    } else {
+        Ok(serde_json::from_slice::<TmjTileset>(&bytes)?.name)
+    }
+}
+
+fn invalid(message: impl Into<String>) -> TiledLoaderError {
+    TiledLoaderError::Invalid(message.into())
+}
+
+// What both file formats boil down to
+struct Map {
+    width: usize,
+    height: usize,
+    tile_size: Vec2,
+    properties: Properties,
+    tilesets: Vec<Tileset>,
+    layers: Vec<(Vec<u32>, Properties)>,
+    objects: Vec<Object>,
+}
+
+struct Tileset {
+    first_gid: u32,
+    name: String,
+    source: Option<String>,
+}
+
+struct Object {
+    kind: String,
+    name: String,
+    // Pixels, from the top left of the map
+    position: Vec2,
+    size: Vec2,
+    // Tile objects are anchored at their bottom left rather than top left
+    is_tile: bool,
+    properties: Properties,
+}
+
+impl Map {
+    fn into_level_data(self) -> Result<LevelData, TiledLoaderError> {
+        let mut tilesets: Vec<&Tileset> = self.tilesets.iter().collect();
+        tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+        // Our layers draw from a single sheet, so a layer mixing tilesets is split up
+        let mut layers = Vec::new();
+        for (gids, properties) in self.layers.iter() {
+            if gids.len() != self.width * self.height {
+                return Err(invalid(format!(
+                    "tile layer has {} tiles, expected {}",
+                    gids.len(),
+                    self.width * self.height
+                )));
+            }
+            let mut split: Vec<TileLayer> = Vec::new();
+
+            for (i, gid) in gids.iter().enumerate() {
+                let gid = gid & !FLIP_FLAGS;
+                if gid == 0 {
+                    continue;
+                }
+                let Some(tileset) = tilesets.iter().rev().find(|t| t.first_gid <= gid) else {
+                    return Err(invalid(format!("tile {} isn't in any tileset", gid)));
+                };
+
+                let layer = match split.iter().position(|l| l.tileset == tileset.name) {
+                    Some(index) => &mut split[index],
+                    None => {
+                        split.push(TileLayer {
+                            tileset: tileset.name.clone(),
+                            tiles: vec![None; gids.len()],
+                            properties: properties.clone(),
+                        });
+                        split.last_mut().unwrap()
+                    }
+                };
+                // Tiled rows run top to bottom
+                let (x, row) = (i % self.width, i / self.width);
+                let y = self.height - 1 - row;
+                layer.tiles[y * self.width + x] = Some((gid - tileset.first_gid) as usize);
+            }
+            layers.extend(split);
+        }
+
+        let map_height = self.height as f32 * self.tile_size.y;
+        let objects = self
+            .objects
+            .into_iter()
+            .map(|object| {
+                let top = if object.is_tile {
+                    object.position.y - object.size.y
+                } else {
+                    object.position.y
+                };
+                let centre = Vec2::new(
+                    object.position.x + object.size.x / 2.,
+                    map_height - (top + object.size.y / 2.),
+                );
+                LevelObject {
+                    kind: object.kind,
+                    name: object.name,
+                    position: centre / self.tile_size - Vec2::splat(0.5),
+                    size: object.size / self.tile_size,
+                    properties: object.properties,
+                }
+            })
+            .collect();
+
+        LevelData::from_map(self.width, self.height, layers, objects, &self.properties)
+            .map_err(invalid)
+    }
+}
+
+#[derive(Deserialize)]
+struct TmjMap {
+    width: usize,
+    height: usize,
+    tilewidth: f32,
+    tileheight: f32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+    layers: Vec<TmjLayer>,
+    tilesets: Vec<TmjTilesetRef>,
+}
+
+#[derive(Deserialize)]
+struct TmjProperty {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TmjLayer {
+    Tilelayer(TmjTileLayer),
+    Objectgroup(TmjObjectGroup),
+    Group(TmjGroup),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct TmjTileLayer {
+    data: TmjData,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    compression: Option<String>,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TmjData {
+    Gids(Vec<u32>),
+    Encoded(String),
+}
+
+#[derive(Deserialize)]
+struct TmjObjectGroup {
+    objects: Vec<TmjObject>,
+}
+
+#[derive(Deserialize)]
+struct TmjGroup {
+    layers: Vec<TmjLayer>,
+}
+
+#[derive(Deserialize)]
+struct TmjObject {
+    #[serde(default)]
+    name: String,
+    // Tiled 1.9 renamed "type" to "class"
+    #[serde(default, alias = "class")]
+    #[serde(rename = "type")]
+    kind: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+}
+
+#[derive(Deserialize)]
+struct TmjTilesetRef {
+    firstgid: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    source: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TmjTileset {
+    name: String,
+}
+
+fn parse_tmj(map: TmjMap) -> Result<Map, TiledLoaderError> {
+    if map.infinite {
+        return Err(invalid("infinite maps aren't supported"));
+    }
+
+    let mut out = Map {
+        width: map.width,
+        height: map.height,
+        tile_size: Vec2::new(map.tilewidth, map.tileheight),
+        properties: tmj_properties(map.properties),
+        tilesets: map
+            .tilesets
+            .into_iter()
+            .map(|tileset| Tileset {
+                first_gid: tileset.firstgid,
+                name: tileset.name,
+                source: tileset.source,
+            })
+            .collect(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+    add_tmj_layers(&mut out, map.layers)?;
+    Ok(out)
+}
+
+fn add_tmj_layers(map: &mut Map, layers: Vec<TmjLayer>) -> Result<(), TiledLoaderError> {
+    for layer in layers {
+        match layer {
+            TmjLayer::Tilelayer(layer) => {
+                let gids = match layer.data {
+                    TmjData::Gids(gids) => gids,
+                    TmjData::Encoded(data) => {
+                        if layer.encoding.as_deref() != Some("base64") {
+                            return Err(invalid("tile data must be csv or base64"));
+                        }
+                        decode_base64(&data, layer.compression.as_deref())?
+                    }
+                };
+                map.layers.push((gids, tmj_properties(layer.properties)));
+            }
+            TmjLayer::Objectgroup(group) => {
+                for object in group.objects {
+                    map.objects.push(Object {
+                        kind: object.kind,
+                        name: object.name,
+                        position: Vec2::new(object.x, object.y),
+                        size: Vec2::new(object.width, object.height),
+                        is_tile: object.gid.is_some(),
+                        properties: tmj_properties(object.properties),
+                    });
+                }
+            }
+            TmjLayer::Group(group) => add_tmj_layers(map, group.layers)?,
+            TmjLayer::Other => {}
+        }
+    }
+    Ok(())
+}
+
+fn tmj_properties(properties: Vec<TmjProperty>) -> Properties {
+    properties
+        .into_iter()
+        .filter_map(|property| {
+            let value = match (property.kind.as_str(), property.value) {
+                ("bool", serde_json::Value::Bool(b)) => PropertyValue::Bool(b),
+                ("int", serde_json::Value::Number(n)) => PropertyValue::Int(n.as_i64()?),
+                ("float", serde_json::Value::Number(n)) => PropertyValue::Float(n.as_f64()?),
+                ("string" | "color" | "file", serde_json::Value::String(s)) => {
+                    PropertyValue::String(s)
+                }
+                // Object references and custom classes have nothing to map to
+                _ => return None,
+            };
+            Some((property.name, value))
+        })
+        .collect()
+}
+
+fn parse_tmx(doc: &roxmltree::Document) -> Result<Map, TiledLoaderError> {
+    let root = doc.root_element();
+    if attr(root, "infinite").is_ok_and(|infinite| infinite == "1") {
+        return Err(invalid("infinite maps aren't supported"));
+    }
+
+    let mut map = Map {
+        width: parse_attr(root, "width")?,
+        height: parse_attr(root, "height")?,
+        tile_size: Vec2::new(
+            parse_attr(root, "tilewidth")?,
+            parse_attr(root, "tileheight")?,
+        ),
+        properties: tmx_properties(root)?,
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+    for tileset in root.children().filter(|n| n.has_tag_name("tileset")) {
+        map.tilesets.push(Tileset {
+            first_gid: parse_attr(tileset, "firstgid")?,
+            name: tileset.attribute("name").unwrap_or_default().to_string(),
+            source: tileset.attribute("source").map(str::to_string),
+        });
+    }
+    add_tmx_layers(&mut map, root)?;
+    Ok(map)
+}
+
+fn add_tmx_layers(map: &mut Map, parent: roxmltree::Node) -> Result<(), TiledLoaderError> {
+    for node in parent.children().filter(roxmltree::Node::is_element) {
+        match node.tag_name().name() {
+            "layer" => {
+                let data = node
+                    .children()
+                    .find(|n| n.has_tag_name("data"))
+                    .ok_or_else(|| invalid("tile layer has no data"))?;
+                let text = data.text().unwrap_or_default().trim();
+                let gids = match data.attribute("encoding") {
+                    Some("csv") => text
+                        .split(',')
+                        .map(|gid| gid.trim().parse::<u32>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| invalid(format!("bad csv tile data: {}", e)))?,
+                    Some("base64") => decode_base64(text, data.attribute("compression"))?,
+                    Some(encoding) => {
+                        return Err(invalid(format!("unknown tile encoding {:?}", encoding)))
+                    }
+                    None => data
+                        .children()
+                        .filter(|n| n.has_tag_name("tile"))
+                        .map(|tile| tile.attribute("gid").map_or(Ok(0), str::parse::<u32>))
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| invalid(format!("bad tile gid: {}", e)))?,
+                };
+                map.layers.push((gids, tmx_properties(node)?));
+            }
+            "objectgroup" => {
+                for object in node.children().filter(|n| n.has_tag_name("object")) {
+                    let kind = object
+                        .attribute("class")
+                        .or_else(|| object.attribute("type"))
+                        .unwrap_or_default();
+                    let size = |name| object.attribute(name).map_or(Ok(0.), str::parse::<f32>);
+                    map.objects.push(Object {
+                        kind: kind.to_string(),
+                        name: object.attribute("name").unwrap_or_default().to_string(),
+                        position: Vec2::new(parse_attr(object, "x")?, parse_attr(object, "y")?),
+                        size: Vec2::new(
+                            size("width").map_err(|e| invalid(e.to_string()))?,
+                            size("height").map_err(|e| invalid(e.to_string()))?,
+                        ),
+                        is_tile: object.attribute("gid").is_some(),
+                        properties: tmx_properties(object)?,
+                    });
+                }
+            }
+            "group" => add_tmx_layers(map, node)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn tmx_properties(node: roxmltree::Node) -> Result<Properties, TiledLoaderError> {
+    let mut properties = Properties::default();
+    let Some(list) = node.children().find(|n| n.has_tag_name("properties")) else {
+        return Ok(properties);
+    };
+
+    for property in list.children().filter(|n| n.has_tag_name("property")) {
+        let name = attr(property, "name")?;
+        // Multi-line strings are stored as text rather than in "value"
+        let value = property
+            .attribute("value")
+            .or_else(|| property.text())
+            .unwrap_or_default();
+        let bad_value = || invalid(format!("bad value for property {:?}", name));
+
+        let value = match property.attribute("type").unwrap_or("string") {
+            "bool" => PropertyValue::Bool(value.parse().map_err(|_| bad_value())?),
+            "int" => PropertyValue::Int(value.parse().map_err(|_| bad_value())?),
+            "float" => PropertyValue::Float(value.parse().map_err(|_| bad_value())?),
+            "string" | "color" | "file" => PropertyValue::String(value.to_string()),
+            _ => continue,
+        };
+        properties.insert(name.to_string(), value);
+    }
+    Ok(properties)
+}
+
+fn attr<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Result<&'a str, TiledLoaderError> {
+    node.attribute(name).ok_or_else(|| {
+        invalid(format!(
+            "<{}> has no {:?} attribute",
+            node.tag_name().name(),
+            name
+        ))
+    })
+}
+
+fn parse_attr<T: std::str::FromStr>(
+    node: roxmltree::Node,
+    name: &str,
+) -> Result<T, TiledLoaderError> {
+    let value = attr(node, name)?;
+    value.parse().map_err(|_| {
+        invalid(format!(
+            "<{}> has a bad {:?} attribute: {:?}",
+            node.tag_name().name(),
+            name,
+            value
+        ))
+    })
+}
+
+fn decode_base64(data: &str, compression: Option<&str>) -> Result<Vec<u32>, TiledLoaderError> {
+    let bytes = STANDARD
+        .decode(data.trim())
+        .map_err(|e| invalid(format!("bad base64 tile data: {}", e)))?;
+
+    let bytes = match compression.unwrap_or_default() {
+        "" => bytes,
+        "zlib" => {
+            let mut out = Vec::new();
+            ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut out)?;
+            out
+        }
+        "gzip" => {
+            let mut out = Vec::new();
+            GzDecoder::new(bytes.as_slice()).read_to_end(&mut out)?;
+            out
+        }
+        other => {
+            return Err(invalid(format!(
+                "{:?} compressed tile data isn't supported",
+                other
+            )))
+        }
+    };
+
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{Hazard, PropertyComponents};
+    use flate2::{
+        write::{GzEncoder, ZlibEncoder},
+        Compression,
+    };
+    use std::io::Write;
+
+    // A 3x2 map, top row first: empty above a floor of tiles 0, 1, 0
+    const GIDS: [u32; 6] = [0, 0, 0, 1, 2, 1];
+
+    fn base64(compression: &str) -> String {
+        let bytes: Vec<u8> = GIDS.iter().flat_map(|gid| gid.to_le_bytes()).collect();
+        let bytes = match compression {
+            "zlib" => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+            "gzip" => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+            _ => bytes,
+        };
+        STANDARD.encode(bytes)
+    }
+
+    // `layer` holds the tile layer's data fields
+    fn tmj(layer: &str) -> Result<LevelData, TiledLoaderError> {
+        let text = format!(
+            r#"{{
+                "width": 3, "height": 2, "tilewidth": 100, "tileheight": 100,
+                "properties": [{{"name": "background", "type": "string", "value": "sky"}}],
+                "tilesets": [{{"firstgid": 1, "name": "bricks"}}],
+                "layers": [
+                    {{"type": "tilelayer", {}}},
+                    {{"type": "objectgroup", "objects": [
+                        {{"type": "Player", "x": 0, "y": 0, "width": 100, "height": 100}},
+                        {{"class": "Goal", "x": 200, "y": 0, "width": 100, "height": 100}}
+                    ]}}
+                ]
+            }}"#,
+            layer
+        );
+        parse_tmj(serde_json::from_str(&text)?)?.into_level_data()
+    }
+
+    // `data` is the tile layer's <data> element
+    fn tmx(data: &str) -> Result<LevelData, TiledLoaderError> {
+        let text = format!(
+            r#"<map width="3" height="2" tilewidth="100" tileheight="100">
+                <properties><property name="background" value="sky"/></properties>
+                <tileset firstgid="1" name="bricks"/>
+                <layer>{}</layer>
+                <objectgroup>
+                    <object type="Player" x="0" y="0" width="100" height="100"/>
+                    <object class="Goal" x="200" y="0" width="100" height="100"/>
+                </objectgroup>
+            </map>"#,
+            data
+        );
+        parse_tmx(&roxmltree::Document::parse(&text)?)?.into_level_data()
+    }
+
+    fn assert_level(level: Result<LevelData, TiledLoaderError>) {
+        let level = level.unwrap();
+        assert_eq!((level.width, level.height), (3, 2));
+        assert_eq!(level.layers.len(), 1);
+        assert_eq!(level.layers[0].tileset, "bricks");
+        assert_eq!(
+            level.layers[0].tiles,
+            [Some(0), Some(1), Some(0), None, None, None]
+        );
+        assert_eq!(
+            (level.spawn, level.goal),
+            (Vec2::new(0., 1.), Vec2::new(2., 1.))
+        );
+        assert_eq!(level.background, "sky");
+    }
+
+    fn error(level: Result<LevelData, TiledLoaderError>) -> String {
+        level.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn tmj_encodings() {
+        assert_level(tmj(r#""data": [0, 0, 0, 1, 2, 1]"#));
+        for compression in ["", "zlib", "gzip"] {
+            assert_level(tmj(&format!(
+                r#""data": "{}", "encoding": "base64", "compression": "{}""#,
+                base64(compression),
+                compression
+            )));
+        }
+    }
+
+    #[test]
+    fn hazard_property_adds_a_component() {
+        let level = tmj(r#""data": [0, 0, 0, 1, 2, 1],
+            "properties": [{"name": "hazard", "type": "bool", "value": true}]"#)
+        .unwrap();
+
+        // As chunk streaming does for each tile
+        let mut world = World::new();
+        let tile = world.spawn_empty().id();
+        let property_components = PropertyComponents::default();
+        for (name, value) in level.layers[0].properties.iter() {
+            if let Some(add_component) = property_components.get(name) {
+                add_component(&mut world.commands().entity(tile), value);
+            }
+        }
+        world.flush();
+        assert!(world.entity(tile).contains::<Hazard>());
+    }
+
+    #[test]
+    fn tmx_encodings() {
+        assert_level(tmx(r#"<data encoding="csv">0,0,0,
+            1,2,1</data>"#));
+        assert_level(tmx(&format!(
+            r#"<data encoding="base64">{}</data>"#,
+            base64("")
+        )));
+        for compression in ["zlib", "gzip"] {
+            assert_level(tmx(&format!(
+                r#"<data encoding="base64" compression="{}">{}</data>"#,
+                compression,
+                base64(compression)
+            )));
+        }
+        let tiles: String = GIDS
+            .iter()
+            .map(|gid| format!(r#"<tile gid="{}"/>"#, gid))
+            .collect();
+        assert_level(tmx(&format!("<data>{}</data>", tiles)));
+    }
+
+    #[test]
+    fn external_tilesets() {
+        let tsx = br#"<tileset name="bricks" tilewidth="100" tileheight="100"/>"#;
+        let tsj = br#"{"name": "bricks", "tilewidth": 100}"#;
+        assert_eq!(
+            external_tileset_name("bricks.tsx", tsx.to_vec()).unwrap(),
+            "bricks"
+        );
+        assert_eq!(
+            external_tileset_name("bricks.tsj", tsj.to_vec()).unwrap(),
+            "bricks"
+        );
+        assert!(external_tileset_name("bricks.tsx", b"<tileset/>".to_vec()).is_err());
+    }
+
+    #[test]
+    fn tiles_outside_every_tileset() {
+        let map = Map {
+            width: 1,
+            height: 1,
+            tile_size: Vec2::splat(100.),
+            properties: Properties::default(),
+            tilesets: vec![Tileset {
+                first_gid: 10,
+                name: "bricks".to_string(),
+                source: None,
+            }],
+            layers: vec![(vec![3], Properties::default())],
+            objects: Vec::new(),
+        };
+        assert_eq!(
+            error(map.into_level_data()),
+            "Invalid map, tile 3 isn't in any tileset"
+        );
+    }
+
+    #[test]
+    fn bad_maps() {
+        assert_eq!(
+            error(tmj(r#""data": [0, 0, 0, 1]"#)),
+            "Invalid map, tile layer has 4 tiles, expected 6"
+        );
+        assert_eq!(
+            error(tmj(
+                r#""data": "AAAA", "encoding": "base64", "compression": "zstd""#
+            )),
+            "Invalid map, \"zstd\" compressed tile data isn't supported"
+        );
+        assert_eq!(
+            error(tmj(r#""data": "0,0,0,1,2,1""#)),
+            "Invalid map, tile data must be csv or base64"
+        );
+        assert_eq!(
+            error(tmx(r#"<data encoding="hex">00</data>"#)),
+            "Invalid map, unknown tile encoding \"hex\""
+        );
+        assert_eq!(
+            error(tmx(r#"<data encoding="csv">0,0,0,1,x,1</data>"#)),
+            "Invalid map, bad csv tile data: invalid digit found in string"
+        );
+        let infinite = r#"<map infinite="1" width="3" height="2"/>"#;
+        assert_eq!(
+            parse_tmx(&roxmltree::Document::parse(infinite).unwrap())
+                .err()
+                .unwrap()
+                .to_string(),
+            "Invalid map, infinite maps aren't supported"
+        );
+    }
+}
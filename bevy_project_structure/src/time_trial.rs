@@ -0,0 +1,280 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs};
+
+use crate::{
+    animation::AnimationSet,
+    campaign::{CurrentLevel, GoalReached},
+    interpolation::Interpolated,
+    level::{tile_to_world, ActiveLevel, LevelSetup},
+    level_data::LevelData,
+    levelgen::LevelSeed,
+    loading::{despawn_with, GameAssets},
+    player::Player,
+    GameState, FIXED_HZ, GHOSTS_PATH, GHOST_ALPHA, SPLITS,
+};
+
+// Where the player was and how they looked on one tick of a run
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct GhostFrame {
+    position: Vec2,
+    index: usize,
+    flip_x: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Run {
+    // Ticks from the start until each checkpoint was passed, ending with the goal
+    splits: Vec<u32>,
+    frames: Vec<GhostFrame>,
+}
+
+// Fastest finished run through each level, saved between sessions
+#[derive(Resource, Serialize, Deserialize, Default, Deref, DerefMut)]
+#[serde(transparent)]
+struct BestRuns(BTreeMap<String, Run>);
+
+#[derive(Resource)]
+struct CurrentRun {
+    level: String,
+    // How far along the level (in world x) each split is taken, before the goal
+    checkpoints: Vec<f32>,
+    run: Run,
+    finished: bool,
+    // The best run as it was when this one started
+    ghost: Option<Run>,
+}
+
+// Plays back the best run alongside the player
+#[derive(Component)]
+struct Ghost;
+
+#[derive(Component)]
+struct SplitTimer;
+
+// Times each level and races the player against their best run, shown as a ghost
+pub struct TimeTrialPlugin {
+    pub enabled: bool,
+}
+
+impl Plugin for TimeTrialPlugin {
+    fn build(&self, app: &mut App) {
+        if !self.enabled {
+            return;
+        }
+        app.insert_resource(load_best_runs())
+            .add_systems(OnEnter(GameState::Playing), start_run.after(LevelSetup))
+            .add_systems(
+                FixedUpdate,
+                (record_run, move_ghost)
+                    .chain()
+                    .after(AnimationSet)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(Update, show_splits.run_if(in_state(GameState::Playing)))
+            .add_systems(
+                OnExit(GameState::Playing),
+                (despawn_with::<Ghost>, despawn_with::<SplitTimer>),
+            );
+    }
+}
+
+fn load_best_runs() -> BestRuns {
+    let Ok(text) = fs::read_to_string(GHOSTS_PATH) else {
+        return BestRuns::default();
+    };
+    ron::from_str(&text).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable ghosts {}: {}", GHOSTS_PATH, e);
+        BestRuns::default()
+    })
+}
+
+fn save_best_runs(best_runs: &BestRuns) {
+    let result = ron::to_string(best_runs)
+        .map_err(|e| e.to_string())
+        .and_then(|text| fs::write(GHOSTS_PATH, text).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        warn!("Could not save ghosts to {}: {}", GHOSTS_PATH, e);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_run(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    current_level: Res<CurrentLevel>,
+    seed: Option<Res<LevelSeed>>,
+    levels: Res<Assets<LevelData>>,
+    active_level: Res<ActiveLevel>,
+    best_runs: Res<BestRuns>,
+) {
+    // Generated levels are told apart by their seed
+    let mut level = game_assets.campaign()[**current_level].clone();
+    if let Some(seed) = seed {
+        level = format!("{} {}", level, **seed);
+    }
+
+    let level_data = levels.get(&**active_level).unwrap();
+    let start = tile_to_world(level_data.spawn).x;
+    let goal = tile_to_world(level_data.goal).x;
+    let checkpoints = (1..SPLITS)
+        .map(|i| start + (goal - start) * i as f32 / SPLITS as f32)
+        .collect();
+
+    let ghost = best_runs.get(&level).cloned();
+    if let Some(first) = ghost.as_ref().and_then(|ghost| ghost.frames.first()) {
+        // Just behind the player
+        let position = first.position.extend(899.);
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::srgba(1., 1., 1., GHOST_ALPHA),
+                    flip_x: first.flip_x,
+                    ..default()
+                },
+                texture: game_assets.image("player").expect("player checked at load"),
+                transform: Transform::from_translation(position),
+                ..default()
+            },
+            TextureAtlas {
+                layout: game_assets
+                    .layout("player")
+                    .expect("player checked at load"),
+                index: first.index,
+            },
+            Interpolated::new(position),
+            Ghost,
+        ));
+    }
+
+    commands.spawn((
+        TextBundle::from_section("", TextStyle::default()).with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            left: Val::Px(10.),
+            ..default()
+        }),
+        SplitTimer,
+    ));
+
+    commands.insert_resource(CurrentRun {
+        level,
+        checkpoints,
+        run: Run::default(),
+        finished: false,
+        ghost,
+    });
+}
+
+fn record_run(
+    player: Query<(&Transform, &TextureAtlas, &Sprite), With<Player>>,
+    mut current_run: ResMut<CurrentRun>,
+    mut best_runs: ResMut<BestRuns>,
+    mut goal_reached: EventReader<GoalReached>,
+) {
+    if current_run.finished {
+        return;
+    }
+    let (transform, texture_atlas, sprite) = player.single();
+    let CurrentRun {
+        level,
+        checkpoints,
+        run,
+        finished,
+        ..
+    } = &mut *current_run;
+
+    let position = transform.translation.truncate();
+    run.frames.push(GhostFrame {
+        position,
+        index: texture_atlas.index,
+        flip_x: sprite.flip_x,
+    });
+    let ticks = run.frames.len() as u32;
+    while checkpoints
+        .get(run.splits.len())
+        .is_some_and(|&checkpoint| position.x >= checkpoint)
+    {
+        run.splits.push(ticks);
+    }
+
+    if goal_reached.read().last().is_none() {
+        return;
+    }
+    run.splits.resize(checkpoints.len() + 1, ticks);
+    *finished = true;
+
+    let best = best_runs.get(level.as_str());
+    if best.map_or(true, |best| run.frames.len() < best.frames.len()) {
+        info!("New best time for {}: {}", level, format_ticks(ticks));
+        best_runs.insert(level.clone(), run.clone());
+        save_best_runs(&best_runs);
+    }
+}
+
+// The ghost is on the same tick of its run as the player, and waits at the goal once it's done
+fn move_ghost(
+    mut ghost: Query<(&mut Transform, &mut TextureAtlas, &mut Sprite), With<Ghost>>,
+    current_run: Res<CurrentRun>,
+) {
+    let Ok((mut transform, mut texture_atlas, mut sprite)) = ghost.get_single_mut() else {
+        return;
+    };
+    let Some(frames) = current_run.ghost.as_ref().map(|ghost| &ghost.frames) else {
+        return;
+    };
+    let tick = current_run.run.frames.len().clamp(1, frames.len()) - 1;
+    let frame = frames[tick];
+    transform.translation = frame.position.extend(transform.translation.z);
+    texture_atlas.index = frame.index;
+    sprite.flip_x = frame.flip_x;
+}
+
+fn show_splits(mut split_timer: Query<&mut Text, With<SplitTimer>>, current_run: Res<CurrentRun>) {
+    let mut text = split_timer.single_mut();
+    let run = &current_run.run;
+
+    let mut sections = vec![TextSection::new(
+        format!("Time {}\n", format_ticks(run.frames.len() as u32)),
+        TextStyle {
+            font_size: 30.,
+            ..default()
+        },
+    )];
+    for (i, &split) in run.splits.iter().enumerate() {
+        let label = if i == current_run.checkpoints.len() {
+            "Goal".to_string()
+        } else {
+            format!("Split {}", i + 1)
+        };
+        // Ahead of the ghost in green, behind in red
+        let (difference, color) = match current_run
+            .ghost
+            .as_ref()
+            .and_then(|ghost| ghost.splits.get(i))
+        {
+            Some(&best_split) if split <= best_split => (
+                format!(" -{}", format_ticks(best_split - split)),
+                Color::srgb(0.4, 1., 0.4),
+            ),
+            Some(&best_split) => (
+                format!(" +{}", format_ticks(split - best_split)),
+                Color::srgb(1., 0.4, 0.4),
+            ),
+            None => (String::new(), Color::WHITE),
+        };
+        sections.push(TextSection::new(
+            format!("{} {}", label, format_ticks(split)),
+            TextStyle::default(),
+        ));
+        sections.push(TextSection::new(
+            format!("{}\n", difference),
+            TextStyle { color, ..default() },
+        ));
+    }
+    text.sections = sections;
+}
+
+fn format_ticks(ticks: u32) -> String {
+    format!("{:.2}", ticks as f64 / FIXED_HZ)
+}
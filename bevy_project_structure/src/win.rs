@@ -1,9 +1,10 @@
-use bevy::{asset::LoadState, prelude::*};
+use bevy::prelude::*;
 
-use crate::{loading::LoadingAssets, GameState};
-
-#[derive(Event, Default)]
-pub struct Win;
+use crate::{
+    actions::Action,
+    loading::{despawn_with, GameAssets},
+    GameState,
+};
 
 #[derive(Component)]
 pub struct WinScreen;
@@ -14,31 +15,20 @@ pub struct WinScreenImage(Handle<Image>);
 pub struct WinPlugin;
 impl Plugin for WinPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, load_win)
+        app.add_systems(Update, load_win.run_if(resource_added::<GameAssets>))
             .add_systems(OnEnter(GameState::Win), setup_win)
-            .add_systems(Update, win_event_listener)
-            .add_event::<Win>();
+            .add_systems(Update, return_to_menu.run_if(in_state(GameState::Win)))
+            .add_systems(OnExit(GameState::Win), despawn_with::<WinScreen>);
     }
 }
 
-fn load_win(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut loading_assets: ResMut<LoadingAssets>,
-) {
-    let win_texture_handle = asset_server.load("win.png");
-
-    loading_assets
-        .0
-        .push((win_texture_handle.clone().untyped(), LoadState::NotLoaded));
-    commands.insert_resource(WinScreenImage(win_texture_handle));
+fn load_win(mut commands: Commands, game_assets: Res<GameAssets>) {
+    // Required, so the manifest was checked for it
+    let image = game_assets.image("win").expect("win image checked at load");
+    commands.insert_resource(WinScreenImage(image));
 }
 
-fn setup_win(
-    mut commands: Commands,
-    winscreen_image: Res<WinScreenImage>,
-    mut camera: Query<&mut Transform, With<Camera>>,
-) {
+fn setup_win(mut commands: Commands, winscreen_image: Res<WinScreenImage>) {
     commands
         .spawn(SpriteBundle {
             texture: winscreen_image.0.clone(),
@@ -46,17 +36,10 @@ fn setup_win(
             ..default()
         })
         .insert(WinScreen);
-
-    let mut ct = camera.single_mut();
-    ct.translation.x = 0.;
 }
 
-fn win_event_listener(
-    mut win_event: EventReader<Win>,
-    mut next_state: ResMut<NextState<GameState>>,
-) {
-    if !win_event.is_empty() {
-        next_state.set(GameState::Win);
-        win_event.clear();
+fn return_to_menu(actions: Res<ButtonInput<Action>>, mut next_state: ResMut<NextState<GameState>>) {
+    if actions.any_just_pressed([Action::Confirm, Action::Pause]) {
+        next_state.set(GameState::MainMenu);
     }
 }